@@ -0,0 +1,285 @@
+use crate::sprite;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    Once,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationFrame {
+    /// Index of the frame in the sprite sheet.
+    pub index: usize,
+    /// Seconds the frame stays on screen.
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Clip {
+    pub frames: Vec<AnimationFrame>,
+    #[serde(default)]
+    pub mode: PlayMode,
+    /// Sent by `Animator::update` each time the last frame of the clip ends.
+    pub event: Option<String>,
+}
+
+/// A sprite sheet and the clips that play on it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationSet {
+    pub sheet: String,
+    pub frame_width: f64,
+    pub frame_height: f64,
+    #[serde(default)]
+    pub margin: f64,
+    #[serde(default)]
+    pub spacing: f64,
+    pub clips: BTreeMap<String, Clip>,
+}
+
+impl AnimationSet {
+    /// Reads the clips at `path`, checking they only show frames the sheet
+    /// next to it has.
+    pub fn load(path: &Path) -> Result<AnimationSet, AnimationError> {
+        let content = fs::read_to_string(path).map_err(AnimationError::Io)?;
+        let set: AnimationSet = toml::from_str(&content).map_err(AnimationError::Parse)?;
+        for (name, clip) in set.clips.iter() {
+            if clip.frames.is_empty() {
+                return Err(AnimationError::EmptyClip(name.clone()));
+            }
+            if clip.frames.iter().any(|frame| frame.duration <= 0.0) {
+                return Err(AnimationError::BadDuration(name.clone()));
+            }
+        }
+        if !(set.frame_width > 0.0 && set.frame_height > 0.0) {
+            return Err(AnimationError::BadFrameSize);
+        }
+        let sheet = path.with_file_name(&set.sheet);
+        let (width, height) =
+            image::image_dimensions(&sheet).map_err(|err| AnimationError::Sheet(sheet, err))?;
+        let size = [width as f64, height as f64];
+        let tile = [set.frame_width, set.frame_height];
+        set.check(sprite::regions(size, tile, set.margin, set.spacing).len())?;
+        Ok(set)
+    }
+
+    /// Checks every clip shows one of the `frames` of the sheet.
+    fn check(&self, frames: usize) -> Result<(), AnimationError> {
+        for (name, clip) in self.clips.iter() {
+            if let Some(frame) = clip.frames.iter().find(|frame| frame.index >= frames) {
+                return Err(AnimationError::MissingFrame {
+                    clip: name.clone(),
+                    index: frame.index,
+                    frames,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    EmptyClip(String),
+    BadDuration(String),
+    BadFrameSize,
+    Sheet(PathBuf, image::ImageError),
+    /// A clip shows a frame past the last of the sheet.
+    MissingFrame {
+        clip: String,
+        index: usize,
+        frames: usize,
+    },
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::Io(err) => write!(f, "can't read animations: {}", err),
+            AnimationError::Parse(err) => write!(f, "invalid animations: {}", err),
+            AnimationError::EmptyClip(name) => write!(f, "clip '{}' has no frames", name),
+            AnimationError::BadDuration(name) => {
+                write!(f, "clip '{}' has a frame without a positive duration", name)
+            }
+            AnimationError::BadFrameSize => {
+                write!(f, "frame_width and frame_height must be above 0")
+            }
+            AnimationError::Sheet(path, err) => {
+                write!(f, "can't read sheet '{}': {}", path.display(), err)
+            }
+            AnimationError::MissingFrame {
+                clip,
+                index,
+                frames,
+            } => write!(
+                f,
+                "clip '{}' shows frame {}, but the sheet has {} frames",
+                clip, index, frames
+            ),
+        }
+    }
+}
+
+/// Plays one clip at a time out of a set of clips.
+pub struct Animator {
+    clips: BTreeMap<String, Clip>,
+    clip: String,
+    frame: usize,
+    time: f64,
+    finished: bool,
+}
+
+impl Animator {
+    pub fn new(clips: BTreeMap<String, Clip>, initial: &str) -> Animator {
+        Animator {
+            clips,
+            clip: initial.to_owned(),
+            frame: 0,
+            time: 0.0,
+            finished: false,
+        }
+    }
+
+    /// Switches to clip `name` from its first frame, unless it is already
+    /// playing. Unknown clips are ignored.
+    pub fn play(&mut self, name: &str) {
+        if self.clip != name && self.clips.contains_key(name) {
+            self.clip = name.to_owned();
+            self.frame = 0;
+            self.time = 0.0;
+            self.finished = false;
+        }
+    }
+
+    /// Advances the current clip by `dt` seconds, returning its event if the
+    /// last frame ended.
+    pub fn update(&mut self, dt: f64) -> Option<String> {
+        let clip = self.clips.get(&self.clip)?;
+        if self.finished {
+            return None;
+        }
+
+        let mut event = None;
+        self.time += dt;
+        while self.time >= clip.frames[self.frame].duration {
+            self.time -= clip.frames[self.frame].duration;
+            if self.frame + 1 < clip.frames.len() {
+                self.frame += 1;
+                continue;
+            }
+
+            event = clip.event.clone();
+            match clip.mode {
+                PlayMode::Loop => self.frame = 0,
+                PlayMode::Once => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        event
+    }
+
+    /// Index in the sprite sheet of the frame to draw.
+    pub fn frame(&self) -> usize {
+        self.clips
+            .get(&self.clip)
+            .map_or(0, |clip| clip.frames[self.frame].index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(indices: &[usize], mode: PlayMode, event: Option<&str>) -> Clip {
+        Clip {
+            frames: indices
+                .iter()
+                .map(|index| AnimationFrame {
+                    index: *index,
+                    duration: 0.1,
+                })
+                .collect(),
+            mode,
+            event: event.map(|event| event.to_owned()),
+        }
+    }
+
+    fn animator() -> Animator {
+        let mut clips = BTreeMap::new();
+        clips.insert(
+            "run".to_owned(),
+            clip(&[3, 4, 5], PlayMode::Loop, Some("step")),
+        );
+        clips.insert(
+            "land".to_owned(),
+            clip(&[7, 8], PlayMode::Once, Some("landed")),
+        );
+        Animator::new(clips, "run")
+    }
+
+    #[test]
+    fn looping_clips_start_over() {
+        let mut animator = animator();
+        assert_eq!(animator.frame(), 3);
+        assert_eq!(animator.update(0.15), None);
+        assert_eq!(animator.frame(), 4);
+        assert_eq!(animator.update(0.1), None);
+        assert_eq!(animator.frame(), 5);
+        assert_eq!(animator.update(0.1), Some("step".to_owned()));
+        assert_eq!(animator.frame(), 3);
+    }
+
+    #[test]
+    fn one_shot_clips_stop_on_their_last_frame() {
+        let mut animator = animator();
+        animator.update(0.15);
+        animator.play("land");
+        assert_eq!(animator.frame(), 7);
+        // the event comes once, even when a step skips past several frames
+        assert_eq!(animator.update(0.5), Some("landed".to_owned()));
+        assert_eq!(animator.frame(), 8);
+        assert_eq!(animator.update(1.0), None);
+        assert_eq!(animator.frame(), 8);
+    }
+
+    #[test]
+    fn playing_the_current_clip_keeps_its_frame() {
+        let mut animator = animator();
+        animator.update(0.15);
+        animator.play("run");
+        assert_eq!(animator.frame(), 4);
+        animator.play("swim");
+        assert_eq!(animator.frame(), 4);
+    }
+
+    #[test]
+    fn the_player_animations_load() {
+        let set = AnimationSet::load(Path::new("assets/player_animations.toml")).unwrap();
+        assert!(set.frame_width > 0.0 && set.frame_height > 0.0);
+        assert!(set.clips.contains_key("idle"));
+    }
+
+    #[test]
+    fn clips_only_show_frames_of_the_sheet() {
+        let mut set = AnimationSet::load(Path::new("assets/player_animations.toml")).unwrap();
+        assert!(set.check(1).is_ok());
+        let fall = clip(&[0, 2], PlayMode::Loop, None);
+        set.clips.insert("fall".to_owned(), fall);
+        let err = set.check(2).unwrap_err().to_string();
+        assert_eq!(err, "clip 'fall' shows frame 2, but the sheet has 2 frames");
+    }
+}
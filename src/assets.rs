@@ -0,0 +1,277 @@
+use crate::campaign::Stage;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+/// An image of the manifest, cut into tiles that become sprites.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextureEntry {
+    pub path: String,
+    /// Width and height of each tile, in pixels.
+    pub tile: [f64; 2],
+    #[serde(default)]
+    pub margin: f64,
+    #[serde(default)]
+    pub spacing: f64,
+    /// Names of the tiles in order, or `None` for a single sprite named
+    /// after the texture.
+    pub sprites: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapEntry {
+    name: Option<String>,
+    path: String,
+}
+
+/// Lists every file the game loads, like `assets/assets.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The manifest file itself, which paths are relative to.
+    #[serde(skip)]
+    file: PathBuf,
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureEntry>,
+    #[serde(default)]
+    fonts: BTreeMap<String, String>,
+    #[serde(default)]
+    maps: Vec<MapEntry>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, ManifestError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| ManifestError::Io(path.to_path_buf(), err))?;
+        let mut manifest: Manifest = toml::from_str(&content)
+            .map_err(|err| ManifestError::Parse(path.to_path_buf(), err))?;
+        manifest.file = path.to_path_buf();
+        Ok(manifest)
+    }
+
+    /// Where `path`, as written in the manifest, is on disk.
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.file.with_file_name(path)
+    }
+
+    /// Names `entry` of this manifest in messages about it.
+    pub fn describe(&self, entry: &str) -> String {
+        format!("{}: {}", self.file.display(), entry)
+    }
+
+    /// The error for `entry` pointing to a file that can't be read.
+    fn missing(&self, entry: String, path: &str, err: io::Error) -> ManifestError {
+        ManifestError::Missing {
+            manifest: self.file.clone(),
+            entry,
+            path: path.to_owned(),
+            err,
+        }
+    }
+
+    /// Path of the font called `name`, which has to exist.
+    pub fn font(&self, name: &str) -> Result<PathBuf, ManifestError> {
+        let entry = format!("font '{}'", name);
+        let file = self.fonts.get(name).ok_or_else(|| ManifestError::Unknown {
+            manifest: self.file.clone(),
+            entry: entry.clone(),
+        })?;
+        let path = self.path(file);
+        fs::metadata(&path).map_err(|err| self.missing(entry, file, err))?;
+        Ok(path)
+    }
+
+    /// The maps in the order they are played, the first one unless
+    /// another is given.
+    pub fn stages(&self) -> Result<Vec<Stage>, ManifestError> {
+        if self.maps.is_empty() {
+            return Err(ManifestError::Unknown {
+                manifest: self.file.clone(),
+                entry: "maps".to_owned(),
+            });
+        }
+        let stages = self.maps.iter().map(|entry| {
+            let map = self.path(&entry.path);
+            let name = entry.name.clone().unwrap_or_else(|| {
+                let stem = map.file_stem().unwrap_or_default();
+                stem.to_string_lossy().into_owned()
+            });
+            Stage { name, map }
+        });
+        Ok(stages.collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// An entry the game needs isn't in the manifest.
+    Unknown {
+        manifest: PathBuf,
+        entry: String,
+    },
+    /// A file an entry points to can't be read.
+    Missing {
+        manifest: PathBuf,
+        entry: String,
+        path: String,
+        err: io::Error,
+    },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ManifestError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ManifestError::Unknown { manifest, entry } => {
+                write!(f, "{}: no {} listed", manifest.display(), entry)
+            }
+            ManifestError::Missing {
+                manifest,
+                entry,
+                path,
+                err,
+            } => write!(
+                f,
+                "{}: {}: can't read '{}': {}",
+                manifest.display(),
+                entry,
+                path,
+                err
+            ),
+        }
+    }
+}
+
+/// A shared asset, which stays loaded for as long as any handle to it is
+/// kept.
+pub struct Handle<T>(Rc<T>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle(Rc::clone(&self.0))
+    }
+}
+
+/// Handles are equal when they share one asset.
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Loaded assets of one type by name, handing out handles to them. It
+/// doesn't keep them loaded itself: an asset goes once its last handle
+/// does.
+pub struct Registry<T> {
+    assets: BTreeMap<String, Weak<T>>,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Registry<T> {
+        Registry {
+            assets: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Registry<T> {
+    /// Adds `asset` as `name`, returning the first handle to it.
+    pub fn insert(&mut self, name: String, asset: T) -> Handle<T> {
+        let handle = Handle(Rc::new(asset));
+        self.assets.insert(name, Rc::downgrade(&handle.0));
+        handle
+    }
+
+    /// A handle to the asset called `name`, if it is still loaded.
+    pub fn get(&self, name: &str) -> Option<Handle<T>> {
+        self.assets.get(name)?.upgrade().map(Handle)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.assets
+            .get(name)
+            .is_some_and(|asset| asset.strong_count() > 0)
+    }
+
+    /// Forgets the assets no handle is left to.
+    pub fn release(&mut self) {
+        self.assets.retain(|_, asset| asset.strong_count() > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_entry_of_the_manifest_exists() {
+        let manifest = Manifest::load(Path::new("assets/assets.toml")).unwrap();
+        for entry in manifest.textures.values() {
+            assert!(manifest.path(&entry.path).exists(), "{}", entry.path);
+        }
+        manifest.font("ui").unwrap();
+        for stage in manifest.stages().unwrap() {
+            assert!(stage.map.exists(), "{}", stage.name);
+        }
+    }
+
+    #[test]
+    fn missing_files_name_their_entry() {
+        let mut manifest = Manifest::load(Path::new("assets/assets.toml")).unwrap();
+        manifest
+            .fonts
+            .insert("title".to_owned(), "nowhere.ttf".to_owned());
+        let err = manifest.font("title").unwrap_err().to_string();
+        assert!(err.starts_with("assets/assets.toml: font 'title': can't read 'nowhere.ttf'"));
+        let err = manifest.font("menu").unwrap_err().to_string();
+        assert_eq!(err, "assets/assets.toml: no font 'menu' listed");
+    }
+
+    #[test]
+    fn handles_share_one_asset() {
+        let mut registry = Registry::default();
+        let first = registry.insert("coin".to_owned(), 7);
+        let handle = registry.get("coin").unwrap();
+        assert_eq!(*handle, 7);
+        assert!(first == handle);
+        assert_eq!(Rc::strong_count(&handle.0), 2);
+        assert!(registry.get("brick").is_none());
+    }
+
+    #[test]
+    fn assets_go_with_their_last_handle() {
+        let mut registry = Registry::default();
+        let coin = registry.insert("coin".to_owned(), 7);
+        let brick = registry.insert("brick".to_owned(), 8);
+        let kept = coin.clone();
+        drop(coin);
+        assert!(registry.contains("coin"));
+        drop(brick);
+        assert!(!registry.contains("brick"));
+        assert!(registry.get("brick").is_none());
+
+        registry.release();
+        assert_eq!(registry.assets.len(), 1);
+        drop(kept);
+        registry.release();
+        assert!(registry.assets.is_empty());
+    }
+}
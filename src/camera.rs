@@ -1,29 +1,141 @@
 use piston_window::*;
+use serde::Deserialize;
 use crate::player::Player;
-use crate::object::Object;
 
+/// How the camera follows the player, tunable per level.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+	/// Size of the box, centered on screen, the player moves in freely.
+	pub dead_zone: [f64; 2],
+	/// How fast the view catches up, per second; 0 snaps straight to it.
+	pub smoothing: f64,
+	/// How far ahead of the player the view leads in the direction it faces.
+	pub look_ahead: f64,
+	/// Only re-center vertically once the player lands, instead of
+	/// following every jump.
+	pub vertical_lock: bool,
+	/// Largest shake offset in pixels, reached at full trauma.
+	pub max_shake: f64,
+	/// Trauma lost per second.
+	pub trauma_decay: f64,
+}
+
+impl Default for CameraSettings {
+	fn default() -> CameraSettings {
+		CameraSettings {
+			dead_zone: [100.0, 100.0],
+			smoothing: 0.0,
+			look_ahead: 0.0,
+			vertical_lock: false,
+			max_shake: 12.0,
+			trauma_decay: 1.5,
+		}
+	}
+}
+
+/// Smooth start and stop for `t` going from 0 to 1.
+fn ease(t: f64) -> f64 {
+	let t = t.clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
+
+/// A scripted move of the view to a point and back to the player.
+struct Pan {
+	from: (f64, f64),
+	to: (f64, f64),
+	/// Zoom when the pan started, and the one to use at `to`, if any.
+	from_zoom: f64,
+	zoom: Option<f64>,
+	duration: f64,
+	hold: f64,
+	elapsed: f64,
+}
+
+impl Pan {
+	/// Whether the pan is heading back, and how far along the current leg it is.
+	fn leg(&self) -> (bool, f64) {
+		let duration = self.duration.max(f64::EPSILON);
+		let back = self.elapsed - self.duration - self.hold;
+		if back < 0.0 {
+			(false, ease(self.elapsed / duration))
+		} else {
+			(true, ease(back / duration))
+		}
+	}
+}
+
+/// A change of zoom over time, keeping `focus` still on screen.
+struct Zoom {
+	from: f64,
+	to: f64,
+	focus: Option<(f64, f64)>,
+	duration: f64,
+	elapsed: f64,
+}
+
+/// Follows the player through the level, which stays in world space; the
+/// camera only decides which part of it is on screen.
 pub struct Camera {
+	/// Dead zone the player can move in without scrolling, in screen space.
 	pub x: f64,
 	pub y: f64,
 	pub w: f64,
 	pub h: f64,
+	/// Size of the level, which the view never goes past.
 	pub max_w: f64,
 	pub max_h: f64,
+	/// Top left corner of the view, in world space.
+	pub view_x: f64,
+	pub view_y: f64,
+	/// Top left corner of the view before the last update, to draw in between.
+	previous_x: f64,
+	previous_y: f64,
+	/// Size of the view, i.e. of the window.
+	pub view_w: f64,
+	pub view_h: f64,
+	/// How many screen pixels a world pixel takes.
+	pub zoom: f64,
+	/// Where the view is heading to, in world space.
+	target_x: f64,
+	target_y: f64,
+	settings: CameraSettings,
+	/// 0 to 1, how hard the screen shakes.
+	trauma: f64,
+	time: f64,
+	pan: Option<Pan>,
+	zooming: Option<Zoom>,
 }
 
 impl Camera {
 	
-	pub fn new(x: f64, y: f64, w: f64, h: f64, max_w: f64, max_h: f64) -> Camera {
+	pub fn new(settings: CameraSettings, max_w: f64, max_h: f64) -> Camera {
+		let [w, h] = settings.dead_zone;
 		Camera{
-			x: x,
-			y: y,
-			w: w,
-			h: h,
-			max_w: max_w,
-			max_h: max_h
+			x: 0.0,
+			y: 0.0,
+			w,
+			h,
+			max_w,
+			max_h,
+			view_x: 0.0,
+			view_y: 0.0,
+			previous_x: 0.0,
+			previous_y: 0.0,
+			view_w: 0.0,
+			view_h: 0.0,
+			zoom: 1.0,
+			target_x: 0.0,
+			target_y: 0.0,
+			settings,
+			trauma: 0.0,
+			time: 0.0,
+			pan: None,
+			zooming: None,
 		}
 	}
 
+	#[allow(dead_code)]
 	pub fn show(&mut self, e: &Event, w: &mut PistonWindow){
 		w.draw_2d(e, |c, g, _d| {
 			Rectangle::new_border([1.0, 0.0, 0.0, 1.0], 1.0)
@@ -36,35 +148,464 @@ impl Camera {
 		});
 	}
 
-	pub fn update(&mut self, player: &mut Player, objects: &mut Vec<Object>){
+	/// Keeps `x`, `y` of a view at `zoom` inside the level.
+	fn clamp_at(&self, x: f64, y: f64, zoom: f64) -> (f64, f64){
+		(
+			x.min(self.max_w - self.view_w / zoom).max(0.0),
+			y.min(self.max_h - self.view_h / zoom).max(0.0),
+		)
+	}
 
-		if player.rect.x <= self.x {
-			player.rect.x = self.x;
-			for object in objects.iter_mut(){
-				object.rect.x -= player.vel.x;
-			}
+	fn clamp(&self, x: f64, y: f64) -> (f64, f64){
+		self.clamp_at(x, y, self.zoom)
+	}
+
+	/// Fits the view to the window, keeping the dead zone in its middle.
+	pub fn resize(&mut self, width: f64, height: f64){
+		self.view_w = width;
+		self.view_h = height;
+		self.x = (width - self.w) / 2.0;
+		self.y = (height - self.h) / 2.0;
+		let (x, y) = self.clamp(self.view_x, self.view_y);
+		self.view_x = x;
+		self.view_y = y;
+	}
+
+	/// Moves the view at once so the player sits in the middle of the dead zone.
+	pub fn focus(&mut self, player: &Player){
+		let center = player.rect.center();
+		let (x, y) = self.clamp(
+			center.x - (self.x + self.w / 2.0) / self.zoom,
+			center.y - (self.y + self.h / 2.0) / self.zoom,
+		);
+		self.target_x = x;
+		self.target_y = y;
+		self.view_x = x;
+		self.view_y = y;
+		self.previous_x = x;
+		self.previous_y = y;
+		self.pan = None;
+	}
+
+	/// Forgets any zoom, pan and shake, and moves the view to the player at
+	/// once.
+	pub fn reset(&mut self, player: &Player){
+		self.zoom = 1.0;
+		self.zooming = None;
+		self.trauma = 0.0;
+		self.focus(player);
+	}
+
+	/// Shakes the screen harder; `amount` adds up to a trauma of 1.
+	pub fn shake(&mut self, amount: f64){
+		self.trauma = (self.trauma + amount).min(1.0);
+	}
+
+	/// Eases the zoom to `zoom` over `duration` seconds, keeping the world
+	/// point `focus` in place on screen, or the middle of the view if `None`.
+	pub fn zoom_to(&mut self, zoom: f64, duration: f64, focus: Option<(f64, f64)>){
+		self.zooming = Some(Zoom {
+			from: self.zoom,
+			to: zoom.max(0.1),
+			focus,
+			duration,
+			elapsed: 0.0,
+		});
+	}
+
+	/// Eases the view over `duration` seconds to center on world point `x`,
+	/// `y`, zoomed to `zoom` if given, stays there `hold` seconds, then eases
+	/// back to following the player.
+	pub fn pan_to(&mut self, x: f64, y: f64, zoom: Option<f64>, duration: f64, hold: f64){
+		let zoom = zoom.map(|zoom| zoom.max(0.1));
+		let at = zoom.unwrap_or(self.zoom);
+		let to = self.clamp_at(x - self.view_w / at / 2.0, y - self.view_h / at / 2.0, at);
+		self.pan = Some(Pan {
+			from: (self.view_x, self.view_y),
+			to,
+			from_zoom: self.zoom,
+			zoom,
+			duration,
+			hold,
+			elapsed: 0.0,
+		});
+	}
+
+	/// Sets the zoom, moving the view so `focus` stays where it is on screen.
+	fn set_zoom(&mut self, zoom: f64, focus: Option<(f64, f64)>){
+		let (fx, fy) = focus.unwrap_or((
+			self.view_x + self.view_w / self.zoom / 2.0,
+			self.view_y + self.view_h / self.zoom / 2.0,
+		));
+		let ratio = self.zoom / zoom;
+		self.view_x = fx - (fx - self.view_x) * ratio;
+		self.view_y = fy - (fy - self.view_y) * ratio;
+		self.target_x = fx - (fx - self.target_x) * ratio;
+		self.target_y = fy - (fy - self.target_y) * ratio;
+		self.zoom = zoom;
+	}
+
+	/// Moves the target view so the player stays in the dead zone.
+	fn follow(&mut self, player: &Player){
+		let center = player.rect.center();
+		let settings = &self.settings;
+		let zoom = self.zoom;
+
+		// the dead zone follows the point the camera looks at, ahead of the player
+		let x = (center.x + settings.look_ahead * player.facing() - self.target_x) * zoom;
+		if x < self.x {
+			self.target_x += (x - self.x) / zoom;
+		}
+		if x > self.x + self.w {
+			self.target_x += (x - (self.x + self.w)) / zoom;
 		}
 
-		if player.rect.y <= self.y {
-			player.rect.y = self.y;
-			for object in objects.iter_mut(){
-				object.rect.y -= player.vel.y;
+		let y = (center.y - self.target_y) * zoom;
+		if settings.vertical_lock {
+			if player.grounded() {
+				self.target_y = center.y - (self.y + self.h / 2.0) / zoom;
+			}
+			// never lose the player off screen while it is in the air
+			let margin = player.rect.scale * zoom;
+			if y < margin {
+				self.target_y += (y - margin) / zoom;
+			}
+			if y > self.view_h - margin {
+				self.target_y += (y - (self.view_h - margin)) / zoom;
+			}
+		} else {
+			if y < self.y {
+				self.target_y += (y - self.y) / zoom;
+			}
+			if y > self.y + self.h {
+				self.target_y += (y - (self.y + self.h)) / zoom;
 			}
 		}
 
-		if player.rect.x + player.rect.scale >= self.x + self.w {
-			player.rect.x = self.x + self.w - player.rect.scale;
-			for object in objects.iter_mut(){
-				object.rect.x -= player.vel.x;
+		let (x, y) = self.clamp(self.target_x, self.target_y);
+		self.target_x = x;
+		self.target_y = y;
+	}
+
+	/// Follows `player` and plays the running effects for `dt` seconds.
+	pub fn update(&mut self, player: &Player, dt: f64){
+		self.previous_x = self.view_x;
+		self.previous_y = self.view_y;
+		self.time += dt;
+		self.trauma = (self.trauma - self.settings.trauma_decay * dt).max(0.0);
+
+		if let Some(mut zoom) = self.zooming.take() {
+			zoom.elapsed += dt;
+			let t = if zoom.duration > 0.0 { zoom.elapsed / zoom.duration } else { 1.0 };
+			self.set_zoom(zoom.from + (zoom.to - zoom.from) * ease(t), zoom.focus);
+			if t < 1.0 {
+				self.zooming = Some(zoom);
 			}
 		}
 
-		if player.rect.y + player.rect.scale >= self.y + self.h {
-			player.rect.y = self.y + self.h - player.rect.scale;
-			for object in objects.iter_mut(){
-				object.rect.y -= player.vel.y;
+		if let Some(mut pan) = self.pan.take() {
+			pan.elapsed += dt;
+			let (returning, t) = pan.leg();
+			if let Some(zoom) = pan.zoom {
+				let (from, to) = if returning { (zoom, pan.from_zoom) } else { (pan.from_zoom, zoom) };
+				self.set_zoom(from + (to - from) * t, None);
 			}
+			// keep following while away, so the way back leads to where the player is now
+			self.follow(player);
+			let (from, to) = if returning {
+				(pan.to, (self.target_x, self.target_y))
+			} else {
+				(pan.from, pan.to)
+			};
+			self.view_x = from.0 + (to.0 - from.0) * t;
+			self.view_y = from.1 + (to.1 - from.1) * t;
+			if !returning || t < 1.0 {
+				self.pan = Some(pan);
+			}
+			return;
 		}
+
+		self.follow(player);
+
+		let t = if self.settings.smoothing > 0.0 {
+			1.0 - (-self.settings.smoothing * dt).exp()
+		} else {
+			1.0
+		};
+		self.view_x += (self.target_x - self.view_x) * t;
+		self.view_y += (self.target_y - self.view_y) * t;
+	}
+
+	/// Offset of the shaking screen, from smooth noise scaled by trauma squared.
+	fn shake_offset(&self) -> (f64, f64){
+		let strength = self.settings.max_shake * self.trauma * self.trauma;
+		let t = self.time;
+		let noise = |a: f64, b: f64| ((t * a).sin() + (t * b).sin() * 0.5) / 1.5;
+		(strength * noise(37.0, 59.0), strength * noise(43.0, 71.0))
+	}
+
+	/// Transform from world space to screen space, with the view `alpha` of
+	/// the way from where it was before the last update to where it is now.
+	pub fn transform(&self, alpha: f64) -> math::Matrix2d {
+		let (sx, sy) = self.shake_offset();
+		let zoom = self.zoom;
+		let x = self.previous_x + (self.view_x - self.previous_x) * alpha;
+		let y = self.previous_y + (self.view_y - self.previous_y) * alpha;
+		math::identity()
+			.trans((sx - x * zoom).round(), (sy - y * zoom).round())
+			.zoom(zoom)
+	}
+
+	/// Whether `[x, y, w, h]`, in world space, is at least partly on screen.
+	pub fn sees(&self, rect: [f64; 4]) -> bool {
+		let [x, y, w, h] = rect;
+		let margin = self.settings.max_shake;
+		x + w >= self.view_x - margin && x <= self.view_x + self.view_w / self.zoom + margin &&
+		y + h >= self.view_y - margin && y <= self.view_y + self.view_h / self.zoom + margin
 	}
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::animation::Animator;
+	use crate::assets::Registry;
+	use crate::grid::Grid;
+	use crate::legend::Legend;
+	use crate::level::Level;
+	use crate::libs::{Rect, Tilemap};
+	use crate::player::PlayerPhysics;
+	use crate::replay::Tick;
+	use crate::world::World;
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	const STEP: f64 = 1.0 / 60.0;
+
+	fn player(x: f64, y: f64) -> Player {
+		let animator = Animator::new(BTreeMap::new(), "idle");
+		Player::new(Rect::new(x, y, 5.0, 0.0, 40.0), animator, PlayerPhysics::default())
+	}
+
+	/// An 800x600 view of a 4000x1000 level, on the player.
+	fn camera(player: &Player) -> Camera {
+		camera_with(CameraSettings::default(), player)
+	}
+
+	fn camera_with(settings: CameraSettings, player: &Player) -> Camera {
+		let mut camera = Camera::new(settings, 4000.0, 1000.0);
+		camera.resize(800.0, 600.0);
+		camera.focus(player);
+		camera
+	}
+
+	/// A world of `map` the player moves in, to follow it into the air
+	/// and back.
+	fn world(map: &str) -> World {
+		let legend = Legend::load(Path::new("assets/tiles.toml")).unwrap();
+		let level = Level::from_tilemap(&Tilemap::parse(map), &legend).unwrap();
+		let animator = Animator::new(BTreeMap::new(), "idle");
+		World::new(level, PlayerPhysics::default(), animator, &Registry::default())
+	}
+
+	fn run(camera: &mut Camera, player: &Player, seconds: f64){
+		for _ in 0..(seconds / STEP).round() as usize {
+			camera.update(player, STEP);
+		}
+	}
+
+	fn close(a: f64, b: f64) -> bool {
+		(a - b).abs() < 1e-6
+	}
+
+	/// Where world point `x`, `y` is on screen.
+	fn on_screen(camera: &Camera, x: f64, y: f64) -> (f64, f64){
+		((x - camera.view_x) * camera.zoom, (y - camera.view_y) * camera.zoom)
+	}
+
+	#[test]
+	fn the_dead_zone_is_centered_and_sized_by_the_settings(){
+		let mut player = player(2000.0, 500.0);
+		let settings = CameraSettings {
+			dead_zone: [200.0, 100.0],
+			..CameraSettings::default()
+		};
+		let mut camera = camera_with(settings, &player);
+		assert_eq!((camera.x, camera.y, camera.w, camera.h), (300.0, 250.0, 200.0, 100.0));
+		let start = (camera.view_x, camera.view_y);
+
+		// free to move up to the edges of the zone
+		player.rect.x += 100.0;
+		player.rect.y += 50.0;
+		run(&mut camera, &player, 0.1);
+		assert_eq!((camera.view_x, camera.view_y), start);
+
+		// and pushing the view past them
+		player.rect.x += 30.0;
+		player.rect.y -= 120.0;
+		run(&mut camera, &player, 0.1);
+		assert!(close(camera.view_x, start.0 + 30.0) && close(camera.view_y, start.1 - 20.0));
+	}
+
+	#[test]
+	fn smoothing_catches_up_over_time(){
+		let mut player = player(2000.0, 500.0);
+		let settings = CameraSettings {
+			smoothing: 5.0,
+			..CameraSettings::default()
+		};
+		let mut camera = camera_with(settings, &player);
+		let start = camera.view_x;
+
+		player.rect.x += 450.0;
+		run(&mut camera, &player, STEP);
+		// 400 pixels out of the dead zone, of which 1 - e^(-5 / 60) are made up
+		let moved = 400.0 * (1.0 - (-5.0 * STEP).exp());
+		assert!(close(camera.view_x, start + moved));
+		run(&mut camera, &player, 0.5);
+		assert!(camera.view_x < start + 400.0);
+		run(&mut camera, &player, 3.0);
+		assert!((camera.view_x - (start + 400.0)).abs() < 0.1);
+	}
+
+	#[test]
+	fn looking_ahead_flips_with_the_player(){
+		let mut player = player(2000.0, 500.0);
+		let settings = CameraSettings {
+			look_ahead: 100.0,
+			..CameraSettings::default()
+		};
+		let mut camera = camera_with(settings, &player);
+		let start = camera.view_x;
+
+		// the point 100 pixels ahead is 50 past the dead zone
+		run(&mut camera, &player, STEP);
+		assert!(close(camera.view_x, start + 50.0));
+
+		player.controller.left = true;
+		player.update(0.0, &[], &Grid::new(40.0, 100, 25));
+		assert_eq!(player.facing(), -1.0);
+		run(&mut camera, &player, STEP);
+		assert!(close(camera.view_x, start - 50.0));
+	}
+
+	#[test]
+	fn a_vertical_lock_only_re_centers_on_the_ground(){
+		// low enough in the level for the view to go up and down
+		let map = format!("{}==P==\n=====\n11111", "=====\n".repeat(15));
+		let mut world = world(&map);
+		for _ in 0..60 {
+			world.step(STEP, Tick::default());
+		}
+		assert!(world.player.grounded());
+		let settings = CameraSettings {
+			vertical_lock: true,
+			..CameraSettings::default()
+		};
+		let mut camera = camera_with(settings, &world.player);
+		let start = camera.view_y;
+
+		// the jump goes well past the dead zone without moving the view
+		let jump = Tick {
+			jump: true,
+			..Tick::default()
+		};
+		let mut top = world.player.rect.y;
+		while !world.player.grounded() || top == world.player.rect.y {
+			world.step(STEP, jump);
+			camera.update(&world.player, STEP);
+			top = top.min(world.player.rect.y);
+			assert_eq!(camera.view_y, start);
+		}
+		assert!(world.player.rect.y - top > 100.0);
+
+		// but standing anywhere else puts the player back in the middle
+		world.player.rect.y -= 30.0;
+		camera.update(&world.player, STEP);
+		assert!(close(camera.view_y, start - 30.0));
+	}
+
+	#[test]
+	fn trauma_adds_up_and_wears_off(){
+		let player = player(2000.0, 500.0);
+		let mut camera = camera(&player);
+		let still = camera.transform(1.0);
+		camera.shake(0.6);
+		camera.shake(0.6);
+		assert_eq!(camera.trauma, 1.0);
+		run(&mut camera, &player, 0.1);
+		assert!(camera.trauma < 1.0 && camera.trauma > 0.0);
+		assert_ne!(camera.transform(1.0), still);
+
+		// trauma_decay is 1.5 a second, so it's gone within one
+		run(&mut camera, &player, 1.0);
+		assert_eq!(camera.trauma, 0.0);
+		assert_eq!(camera.transform(1.0), still);
+	}
+
+	#[test]
+	fn zooming_eases_and_keeps_its_focus_in_place(){
+		let player = player(2000.0, 500.0);
+		let mut camera = camera(&player);
+		let center = player.rect.center();
+		let before = on_screen(&camera, center.x, center.y);
+
+		camera.zoom_to(2.0, 1.0, Some((center.x, center.y)));
+		run(&mut camera, &player, 0.5);
+		assert!(camera.zoom > 1.0 && camera.zoom < 2.0);
+		let during = on_screen(&camera, center.x, center.y);
+		assert!(close(during.0, before.0) && close(during.1, before.1));
+
+		run(&mut camera, &player, 0.6);
+		assert_eq!(camera.zoom, 2.0);
+		assert!(camera.zooming.is_none());
+		let after = on_screen(&camera, center.x, center.y);
+		assert!(close(after.0, before.0) && close(after.1, before.1));
+	}
+
+	#[test]
+	fn panning_shows_a_point_then_comes_back(){
+		let player = player(2000.0, 500.0);
+		let mut camera = camera(&player);
+		let start = (camera.view_x, camera.view_y);
+
+		camera.pan_to(3000.0, 500.0, None, 1.0, 2.0);
+		run(&mut camera, &player, 0.5);
+		assert!(camera.view_x > start.0 && camera.view_x < 2600.0);
+		run(&mut camera, &player, 0.5);
+		assert!(close(camera.view_x, 2600.0) && close(camera.view_y, 200.0));
+		// held there, whatever the player does
+		run(&mut camera, &player, 1.5);
+		assert!(close(camera.view_x, 2600.0));
+
+		run(&mut camera, &player, 2.0);
+		assert!(camera.pan.is_none());
+		assert!(close(camera.view_x, start.0) && close(camera.view_y, start.1));
+	}
+
+	#[test]
+	fn panning_can_zoom_there_and_back(){
+		let player = player(2000.0, 500.0);
+		let mut camera = camera(&player);
+
+		camera.pan_to(3000.0, 500.0, Some(2.0), 1.0, 0.5);
+		run(&mut camera, &player, 1.0);
+		assert!(close(camera.zoom, 2.0));
+		// the point is in the middle of the view, which is half as big
+		assert!(close(camera.view_x, 2800.0) && close(camera.view_y, 350.0));
+
+		run(&mut camera, &player, 2.0);
+		assert!(camera.pan.is_none());
+		assert!(close(camera.zoom, 1.0));
+	}
+
+	#[test]
+	fn pans_stay_inside_the_level(){
+		let player = player(2000.0, 500.0);
+		let mut camera = camera(&player);
+		camera.pan_to(3900.0, 0.0, None, 1.0, 1.0);
+		run(&mut camera, &player, 1.0);
+		assert!(close(camera.view_x, 3200.0) && close(camera.view_y, 0.0));
+	}
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where progress is saved for the user playing, or `None` if the
+/// environment doesn't say where their data goes.
+pub fn save_path() -> Option<PathBuf> {
+    let dir = data_dir(|name| env::var_os(name))?;
+    Some(dir.join(env!("CARGO_PKG_NAME")).join("save.toml"))
+}
+
+/// The directory the platform keeps each user's application data in, with
+/// environment variables read by `var`.
+fn data_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    // relative paths would depend on where the game is run from
+    let var = |name| var(name).map(PathBuf::from).filter(|dir| dir.is_absolute());
+    if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        Some(var("HOME")?.join("Library/Application Support"))
+    } else {
+        var("XDG_DATA_HOME").or_else(|| Some(var("HOME")?.join(".local/share")))
+    }
+}
+
+/// A level of the campaign.
+#[derive(Clone, Debug)]
+pub struct Stage {
+    /// What the level select screen calls it, and what its completion is
+    /// saved as.
+    pub name: String,
+    pub map: PathBuf,
+}
+
+/// What is kept between runs of the game.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Save {
+    /// Names of the stages cleared.
+    completed: BTreeSet<String>,
+}
+
+/// The levels of the game in the order they are played, and which of them
+/// were cleared.
+#[derive(Default)]
+pub struct Campaign {
+    pub stages: Vec<Stage>,
+    /// Index of the stage being played.
+    pub current: usize,
+    save: Save,
+    /// Where completion is saved, or `None` not to save it.
+    path: Option<PathBuf>,
+}
+
+impl Campaign {
+    /// Reads what was cleared before from `path`, if it exists.
+    pub fn new(stages: Vec<Stage>, path: Option<PathBuf>) -> Campaign {
+        let mut save = Save::default();
+        if let Some(path) = path.as_ref().filter(|path| path.exists()) {
+            let read = fs::read_to_string(path).map_err(|err| err.to_string());
+            match read.and_then(|content| toml::from_str(&content).map_err(|err| err.to_string())) {
+                Ok(saved) => save = saved,
+                Err(err) => eprintln!("warning: {}: {}", path.display(), err),
+            }
+        }
+        Campaign {
+            stages,
+            current: 0,
+            save,
+            path,
+        }
+    }
+
+    /// Plays `map` next, adding it after the other stages if it isn't one
+    /// of them.
+    pub fn select(&mut self, map: &Path) {
+        let same = |other: &Path| match (map.canonicalize(), other.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => map == other,
+        };
+        self.current = match self.stages.iter().position(|stage| same(&stage.map)) {
+            Some(idx) => idx,
+            None => {
+                let stem = map.file_stem().unwrap_or_default();
+                self.stages.push(Stage {
+                    name: stem.to_string_lossy().into_owned(),
+                    map: map.to_path_buf(),
+                });
+                self.stages.len() - 1
+            }
+        };
+    }
+
+    /// Map of the stage being played.
+    pub fn map(&self) -> &Path {
+        &self.stages[self.current].map
+    }
+
+    /// Marks the stage being played as cleared, and saves it.
+    pub fn complete(&mut self) {
+        let name = match self.stages.get(self.current) {
+            Some(stage) => stage.name.clone(),
+            None => return,
+        };
+        if !self.save.completed.insert(name) {
+            return;
+        }
+        if let Some(path) = self.path.as_ref() {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let written = toml::to_string(&self.save)
+                .map_err(|err| err.to_string())
+                .and_then(|content| {
+                    fs::create_dir_all(dir)
+                        .and_then(|_| fs::write(path, content))
+                        .map_err(|err| err.to_string())
+                });
+            if let Err(err) = written {
+                eprintln!("warning: can't save to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    pub fn cleared(&self, idx: usize) -> bool {
+        self.stages
+            .get(idx)
+            .is_some_and(|stage| self.save.completed.contains(&stage.name))
+    }
+
+    /// Whether stage `idx` can be played: the first one always can, and
+    /// every other once the one before it is cleared.
+    pub fn unlocked(&self, idx: usize) -> bool {
+        idx == 0 || self.cleared(idx) || self.cleared(idx - 1)
+    }
+
+    /// The stage after the one being played, if there is one.
+    pub fn next(&self) -> Option<usize> {
+        Some(self.current + 1).filter(|idx| *idx < self.stages.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages() -> Vec<Stage> {
+        ["assets/map.txt", "assets/map2.txt", "assets/map.tmj"]
+            .iter()
+            .enumerate()
+            .map(|(idx, map)| Stage {
+                name: format!("level {}", idx + 1),
+                map: PathBuf::from(map),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clearing_a_stage_unlocks_the_next() {
+        let mut campaign = Campaign::new(stages(), None);
+        assert!(campaign.unlocked(0));
+        assert!(!campaign.unlocked(1));
+        campaign.complete();
+        assert!(campaign.cleared(0));
+        assert!(campaign.unlocked(1));
+        assert!(!campaign.unlocked(2));
+        assert_eq!(campaign.next(), Some(1));
+        campaign.current = 2;
+        assert_eq!(campaign.next(), None);
+    }
+
+    #[test]
+    fn completion_is_saved() {
+        let dir = env::temp_dir().join(format!("campaign-{}", std::process::id()));
+        let path = dir.join("save.toml");
+        let mut campaign = Campaign::new(stages(), Some(path.clone()));
+        campaign.select(Path::new("assets/map2.txt"));
+        assert_eq!(campaign.current, 1);
+        campaign.complete();
+
+        let campaign = Campaign::new(stages(), Some(path.clone()));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!campaign.cleared(0));
+        assert!(campaign.cleared(1));
+        assert!(campaign.unlocked(2));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn progress_is_saved_with_the_users_data() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                let found = vars.iter().find(|(var, _)| *var == name);
+                found.map(|(_, value)| OsString::from(value))
+            }
+        };
+        let xdg = env(&[("XDG_DATA_HOME", "/data"), ("HOME", "/home/me")]);
+        assert_eq!(data_dir(xdg), Some(PathBuf::from("/data")));
+        // a relative one is ignored
+        let home = env(&[("XDG_DATA_HOME", "data"), ("HOME", "/home/me")]);
+        let share = PathBuf::from("/home/me/.local/share");
+        assert_eq!(data_dir(home), Some(share));
+        assert_eq!(data_dir(env(&[])), None);
+    }
+
+    #[test]
+    fn maps_not_in_the_campaign_are_played_after_it() {
+        let mut campaign = Campaign::new(stages(), None);
+        campaign.select(Path::new("assets/./map.tmj"));
+        assert_eq!(campaign.current, 2);
+        campaign.select(Path::new("assets/map.toml"));
+        assert_eq!(campaign.current, 3);
+        assert_eq!(campaign.stages[3].name, "map");
+    }
+}
@@ -1,49 +1,192 @@
-use crate::object::Object;
-use crate::player::Player;
 use crate::libs::Rect;
 
+/// Distance under which two edges count as touching.
+const EPSILON: f64 = 1e-6;
+
+/// The side of the moving body that touched a solid.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Interact {
-	LEFT, RIGHT, TOP, BOTTOM
+	Left, Right, Top, Bottom
+}
+
+impl Interact {
+	/// Unit normal of the contact, pointing from the solid towards the body.
+	pub fn normal(&self) -> (f64, f64) {
+		match self {
+			Interact::Left => (1.0, 0.0),
+			Interact::Right => (-1.0, 0.0),
+			Interact::Top => (0.0, 1.0),
+			Interact::Bottom => (0.0, -1.0),
+		}
+	}
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+	pub interact: Interact,
+	/// Id the solid was passed in with.
+	pub target: usize,
+}
+
+#[derive(Default)]
 pub struct Collider {
-	pub interact: Option<(Interact, f64)>
+	pub contacts: Vec<Contact>,
+}
+
+fn overlaps(a_min: f64, a_max: f64, b_min: f64, b_max: f64) -> bool {
+	a_min < b_max - EPSILON && a_max > b_min + EPSILON
 }
 
 impl Collider {
-	
-	pub fn collision(&mut self, player: &Rect, target: &Vec<Object>) -> bool{
-		for object in target.iter(){
-			if object.solid {
-				if player.right() >= object.rect.left() &&
-					player.left() <= object.rect.right() &&
-					player.bottom() >= object.rect.top() &&
-					player.top() <= object.rect.bottom() {
-						let x = player.center().x - object.rect.center().x;
-						let y= player.center().y - object.rect.center().y;
-
-						if y * y > x * x {
-							if y > 0.0 {
-								self.interact = Some((Interact::TOP, object.rect.bottom()));
-								return true;
-							}else{
-								self.interact = Some((Interact::BOTTOM, object.rect.top() - player.scale));
-								return true;
-							}
-						}else{
-							if x > 0.0 {
-								self.interact = Some((Interact::LEFT, object.rect.right()));
-								return true;
-							}else{
-								self.interact = Some((Interact::RIGHT, object.rect.left() - player.scale));
-								return true;
-							}
-						}
-				}
+
+	/// Moves `body` by `dx` then `dy`, stopping each axis at the first solids
+	/// in the way, and records every solid it ends up touching.
+	///
+	/// The whole path of each axis is checked, so fast bodies can't pass
+	/// through thin solids, and solids only touching the body's edge on the
+	/// other axis are ignored, so it slides along seams and corners.
+	pub fn sweep(&mut self, body: &mut Rect, dx: f64, dy: f64, solids: &[(usize, &Rect)]) {
+		self.contacts.clear();
+
+		let mut nearest = dx.abs();
+		let mut hits = Vec::new();
+		for (id, solid) in solids.iter() {
+			if dx == 0.0 || !overlaps(body.top(), body.bottom(), solid.top(), solid.bottom()) {
+				continue;
+			}
+			let gap = if dx > 0.0 {
+				solid.left() - body.right()
+			} else {
+				body.left() - solid.right()
+			};
+			if gap < -EPSILON || gap > nearest + EPSILON {
+				continue;
+			}
+			if gap < nearest - EPSILON {
+				nearest = gap.max(0.0);
+				hits.clear();
+			}
+			hits.push(*id);
+		}
+		body.x += nearest * dx.signum();
+		let interact = if dx > 0.0 { Interact::Right } else { Interact::Left };
+		self.contacts.extend(hits.drain(..).map(|target| Contact { interact, target }));
+
+		let mut nearest = dy.abs();
+		for (id, solid) in solids.iter() {
+			if dy == 0.0 || !overlaps(body.left(), body.right(), solid.left(), solid.right()) {
+				continue;
+			}
+			let gap = if dy > 0.0 {
+				solid.top() - body.bottom()
+			} else {
+				body.top() - solid.bottom()
+			};
+			if gap < -EPSILON || gap > nearest + EPSILON {
+				continue;
+			}
+			if gap < nearest - EPSILON {
+				nearest = gap.max(0.0);
+				hits.clear();
 			}
+			hits.push(*id);
 		}
+		body.y += nearest * dy.signum();
+		let interact = if dy > 0.0 { Interact::Bottom } else { Interact::Top };
+		self.contacts.extend(hits.drain(..).map(|target| Contact { interact, target }));
+	}
+
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tile(col: f64, row: f64) -> Rect {
+		Rect::new(col * 40.0, row * 40.0, 0.0, 0.0, 40.0)
+	}
+
+	fn sweep(body: &mut Rect, dx: f64, dy: f64, tiles: &[Rect]) -> Vec<Contact> {
+		let solids: Vec<(usize, &Rect)> = tiles.iter().enumerate().collect();
+		let mut collider = Collider::default();
+		collider.sweep(body, dx, dy, &solids);
+		collider.contacts
+	}
+
+	#[test]
+	fn lands_on_top_of_a_tile() {
+		let tiles = [tile(0.0, 1.0)];
+		let mut body = Rect::new(0.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 0.0, 10.0, &tiles);
+		assert_eq!(body.y, 0.0);
+		assert_eq!(contacts, vec![Contact { interact: Interact::Bottom, target: 0 }]);
+		assert_eq!(Interact::Bottom.normal(), (0.0, -1.0));
+	}
+
+	#[test]
+	fn fast_fall_does_not_tunnel_through_a_tile() {
+		let tiles = [tile(0.0, 5.0)];
+		let mut body = Rect::new(0.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 0.0, 500.0, &tiles);
+		assert_eq!(body.y, 160.0);
+		assert_eq!(contacts.len(), 1);
+		assert_eq!(contacts[0].interact, Interact::Bottom);
+	}
 
-		false
+	#[test]
+	fn reports_every_tile_under_a_seam() {
+		let tiles = [tile(0.0, 1.0), tile(1.0, 1.0)];
+		let mut body = Rect::new(20.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 0.0, 1.0, &tiles);
+		assert_eq!(body.y, 0.0);
+		assert_eq!(contacts.len(), 2);
+		assert!(contacts.iter().all(|c| c.interact == Interact::Bottom));
 	}
 
-}
\ No newline at end of file
+	#[test]
+	fn walks_across_a_seam_without_snagging() {
+		let tiles = [tile(0.0, 1.0), tile(1.0, 1.0), tile(2.0, 1.0)];
+		let mut body = Rect::new(10.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 30.0, 1.0, &tiles);
+		assert_eq!(body.x, 40.0);
+		assert_eq!(body.y, 0.0);
+		assert!(!contacts.iter().any(|c| c.interact == Interact::Right));
+	}
+
+	#[test]
+	fn stops_flush_against_a_wall() {
+		let tiles = [tile(2.0, 0.0)];
+		let mut body = Rect::new(0.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 100.0, 0.0, &tiles);
+		assert_eq!(body.right(), 80.0);
+		assert_eq!(contacts, vec![Contact { interact: Interact::Right, target: 0 }]);
+	}
+
+	#[test]
+	fn slides_past_a_corner_it_only_grazes() {
+		let tiles = [tile(1.0, 1.0)];
+		// bottom edge level with the top of the tile, moving right
+		let mut body = Rect::new(0.0, 0.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 20.0, 0.0, &tiles);
+		assert_eq!(body.x, 20.0);
+		assert!(contacts.is_empty());
+	}
+
+	#[test]
+	fn lands_on_a_corner_when_moving_diagonally() {
+		let tiles = [tile(1.0, 1.0)];
+		let mut body = Rect::new(0.0, -10.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 20.0, 20.0, &tiles);
+		assert_eq!((body.x, body.y), (20.0, 0.0));
+		assert_eq!(contacts, vec![Contact { interact: Interact::Bottom, target: 0 }]);
+	}
+
+	#[test]
+	fn hits_the_ceiling() {
+		let tiles = [tile(0.0, 0.0)];
+		let mut body = Rect::new(0.0, 50.0, 5.0, 0.0, 40.0);
+		let contacts = sweep(&mut body, 0.0, -30.0, &tiles);
+		assert_eq!(body.y, 40.0);
+		assert_eq!(contacts, vec![Contact { interact: Interact::Top, target: 0 }]);
+	}
+}
@@ -0,0 +1,177 @@
+use crate::assets::Handle;
+use crate::libs::{Rect, Vec2d};
+use crate::sprite::Sprite;
+use std::collections::BTreeMap;
+
+/// Downwards acceleration of debris, in pixels per second squared.
+const DEBRIS_GRAVITY: f64 = 1800.0;
+/// Seconds a bumped block takes to bounce up and come back down.
+const BOUNCE_TIME: f64 = 0.2;
+/// Pixels a bumped block bounces up.
+const BOUNCE_HEIGHT: f64 = 10.0;
+
+/// A piece of a broken block flying off.
+pub struct Piece {
+    pub sprite: Handle<Sprite>,
+    pub rect: Rect,
+    /// Clockwise, in degrees.
+    pub rotation: f64,
+    vel: Vec2d,
+    spin: f64,
+}
+
+/// What only shows on screen of what happens in the world, like debris and
+/// bouncing blocks.
+#[derive(Default)]
+pub struct Effects {
+    pub pieces: Vec<Piece>,
+    /// Seconds each bouncing object has been bouncing for.
+    bounces: BTreeMap<usize, f64>,
+}
+
+impl Effects {
+    /// Breaks a block drawn with `sprite` over `rect` into four pieces.
+    pub fn shatter(&mut self, sprite: &Handle<Sprite>, rect: &Rect) {
+        let half = rect.scale / 2.0;
+        for (i, (sx, sy)) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .iter()
+            .enumerate()
+        {
+            let col = (i % 2) as f64;
+            let row = (i / 2) as f64;
+            self.pieces.push(Piece {
+                sprite: sprite.clone(),
+                rect: Rect::new(rect.x + col * half, rect.y + row * half, 0.0, 0.0, half),
+                rotation: 0.0,
+                vel: Vec2d {
+                    x: sx * 120.0,
+                    y: if *sy < 0.0 { -550.0 } else { -400.0 },
+                },
+                spin: sx * 720.0,
+            });
+        }
+    }
+
+    /// Flips something drawn with `sprite` over `rect` upside down and
+    /// drops it off the screen.
+    pub fn knock_off(&mut self, sprite: &Handle<Sprite>, rect: &Rect) {
+        self.pieces.push(Piece {
+            sprite: sprite.clone(),
+            rect: rect.clone(),
+            rotation: 180.0,
+            vel: Vec2d { x: 0.0, y: -300.0 },
+            spin: 0.0,
+        });
+    }
+
+    pub fn bounce(&mut self, id: usize) {
+        self.bounces.insert(id, 0.0);
+    }
+
+    /// How far up object `id` is drawn from where it is.
+    pub fn offset(&self, id: usize) -> f64 {
+        self.bounces.get(&id).map_or(0.0, |time| {
+            let t = time / BOUNCE_TIME;
+            BOUNCE_HEIGHT * 4.0 * t * (1.0 - t)
+        })
+    }
+
+    /// Moves everything on by `dt` seconds, dropping debris once it falls
+    /// below `floor`.
+    pub fn update(&mut self, dt: f64, floor: f64) {
+        for piece in self.pieces.iter_mut() {
+            piece.vel.add(0.0, DEBRIS_GRAVITY * dt);
+            piece.rect.x += piece.vel.x * dt;
+            piece.rect.y += piece.vel.y * dt;
+            piece.rotation += piece.spin * dt;
+        }
+        self.pieces.retain(|piece| piece.rect.y < floor);
+
+        for time in self.bounces.values_mut() {
+            *time += dt;
+        }
+        self.bounces.retain(|_, time| *time < BOUNCE_TIME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Registry;
+
+    const STEP: f64 = 1.0 / 60.0;
+
+    fn run(effects: &mut Effects, seconds: f64, floor: f64) {
+        for _ in 0..(seconds / STEP).round() as usize {
+            effects.update(STEP, floor);
+        }
+    }
+
+    #[test]
+    fn shattered_blocks_fly_apart_in_quarters() {
+        let brick = Registry::default().insert("brick".to_owned(), Sprite::default());
+        let mut effects = Effects::default();
+        effects.shatter(&brick, &Rect::new(80.0, 40.0, 0.0, 0.0, 40.0));
+        let corners: Vec<_> = effects
+            .pieces
+            .iter()
+            .map(|piece| (piece.rect.x, piece.rect.y, piece.rect.scale))
+            .collect();
+        assert_eq!(
+            corners,
+            [
+                (80.0, 40.0, 20.0),
+                (100.0, 40.0, 20.0),
+                (80.0, 60.0, 20.0),
+                (100.0, 60.0, 20.0)
+            ]
+        );
+        assert!(effects.pieces.iter().all(|piece| piece.sprite == brick));
+
+        effects.update(STEP, 1000.0);
+        let pieces = &effects.pieces;
+        // left pieces fly left spinning one way, right pieces the other
+        assert!(pieces[0].rect.x < 80.0 && pieces[0].rotation < 0.0);
+        assert!(pieces[1].rect.x > 100.0 && pieces[1].rotation > 0.0);
+        // and the top ones go up faster
+        assert!(pieces[0].rect.y - 40.0 < pieces[2].rect.y - 60.0);
+    }
+
+    #[test]
+    fn debris_is_dropped_once_below_the_floor() {
+        let mut sprites = Registry::default();
+        let brick = sprites.insert("brick".to_owned(), Sprite::default());
+        let walker = sprites.insert("walker".to_owned(), Sprite::default());
+        let mut effects = Effects::default();
+        effects.shatter(&brick, &Rect::new(80.0, 40.0, 0.0, 0.0, 40.0));
+        effects.knock_off(&walker, &Rect::new(0.0, 40.0, 5.0, 0.0, 40.0));
+        assert_eq!(effects.pieces.len(), 5);
+        assert_eq!(effects.pieces[4].rotation, 180.0);
+
+        // still rising at first
+        run(&mut effects, 0.1, 400.0);
+        assert_eq!(effects.pieces.len(), 5);
+        assert!(effects.pieces.iter().all(|piece| piece.rect.y < 60.0));
+
+        run(&mut effects, 2.0, 400.0);
+        assert!(effects.pieces.is_empty());
+    }
+
+    #[test]
+    fn bumped_objects_bounce_up_and_back() {
+        let mut effects = Effects::default();
+        effects.bounce(3);
+        assert_eq!(effects.offset(3), 0.0);
+        assert_eq!(effects.offset(4), 0.0);
+
+        effects.update(BOUNCE_TIME / 2.0, 400.0);
+        assert!((effects.offset(3) - BOUNCE_HEIGHT).abs() < 1e-9);
+        effects.update(BOUNCE_TIME / 4.0, 400.0);
+        let falling = effects.offset(3);
+        assert!(falling > 0.0 && falling < BOUNCE_HEIGHT);
+
+        effects.update(BOUNCE_TIME / 4.0, 400.0);
+        assert_eq!(effects.offset(3), 0.0);
+        assert!(effects.bounces.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::assets::Handle;
+use crate::collider::Collider;
+use crate::grid::Grid;
+use crate::legend::EnemyKind;
+use crate::libs::{Rect, Vec2d};
+use crate::object::Object;
+use crate::sprite::Sprite;
+
+/// Downwards acceleration of walkers and hoppers, in pixels per second
+/// squared.
+const GRAVITY: f64 = 1200.0;
+/// Pixels per second walkers walk, and hoppers move while in the air.
+const WALK_SPEED: f64 = 60.0;
+/// Speed hoppers leave the ground at, and seconds they wait between hops.
+const HOP_SPEED: f64 = 450.0;
+const HOP_INTERVAL: f64 = 1.0;
+/// Pixels per second flyers fly along their path.
+const FLY_SPEED: f64 = 80.0;
+
+/// Something moving about the level on its own that the player has to stomp
+/// or avoid.
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub sprite: Option<Handle<Sprite>>,
+    pub rect: Rect,
+    /// Whether it was stomped, and no longer moves or touches the player.
+    pub defeated: bool,
+    /// Where it was before the last update, to draw in between.
+    previous: Vec2d,
+    /// Where it started, which flyers keep coming back to.
+    start: Vec2d,
+    vel: Vec2d,
+    /// 1 when heading right, -1 when heading left.
+    direction: f64,
+    collider: Collider,
+    ground: bool,
+    /// Seconds a hopper has stood still for, or how far along its path and
+    /// back a flyer is, from 0 to 2.
+    timer: f64,
+}
+
+impl Enemy {
+    pub fn new(kind: EnemyKind, sprite: Option<Handle<Sprite>>, rect: Rect) -> Enemy {
+        Enemy {
+            kind,
+            sprite,
+            defeated: false,
+            previous: Vec2d {
+                x: rect.x,
+                y: rect.y,
+            },
+            start: Vec2d {
+                x: rect.x,
+                y: rect.y,
+            },
+            rect,
+            vel: Vec2d::new(),
+            direction: -1.0,
+            collider: Collider::default(),
+            ground: false,
+            timer: 0.0,
+        }
+    }
+
+    /// Moves the enemy by one simulation step of `dt` seconds.
+    pub fn update(&mut self, dt: f64, objects: &[Object], grid: &Grid) {
+        self.previous = Vec2d {
+            x: self.rect.x,
+            y: self.rect.y,
+        };
+        match self.kind {
+            EnemyKind::Walker => {
+                self.vel.x = self.direction * WALK_SPEED;
+                self.fall(dt, objects, grid);
+                // turn around rather than walk off a ledge
+                let ahead = if self.direction > 0.0 {
+                    self.rect.right() + 1.0
+                } else {
+                    self.rect.left() - 1.0
+                };
+                if self.ground && !grid.solid_at(objects, ahead, self.rect.bottom() + 1.0) {
+                    self.direction = -self.direction;
+                }
+            }
+            EnemyKind::Hopper => {
+                if self.ground {
+                    self.vel.x = 0.0;
+                    self.timer += dt;
+                    if self.timer >= HOP_INTERVAL {
+                        self.timer = 0.0;
+                        self.vel.x = self.direction * WALK_SPEED;
+                        self.vel.y = -HOP_SPEED;
+                    }
+                }
+                self.fall(dt, objects, grid);
+            }
+            EnemyKind::Flyer { dx, dy } => {
+                let (dx, dy) = (dx * 40.0, dy * 40.0);
+                let length = dx.hypot(dy);
+                if length > 0.0 {
+                    self.timer = (self.timer + FLY_SPEED * dt / length) % 2.0;
+                }
+                let along = if self.timer < 1.0 {
+                    self.timer
+                } else {
+                    2.0 - self.timer
+                };
+                let x = self.start.x + dx * along;
+                if x != self.rect.x {
+                    self.direction = (x - self.rect.x).signum();
+                }
+                self.rect.x = x;
+                self.rect.y = self.start.y + dy * along;
+            }
+        }
+    }
+
+    /// Falls and moves by its velocity, turning around at walls.
+    fn fall(&mut self, dt: f64, objects: &[Object], grid: &Grid) {
+        self.vel.y += GRAVITY * dt;
+        let (dx, dy) = (self.vel.x * dt, self.vel.y * dt);
+        let solids = grid.solids(objects, &self.rect, dx, dy);
+        self.collider.sweep(&mut self.rect, dx, dy, &solids);
+
+        self.ground = false;
+        for contact in self.collider.contacts.iter() {
+            let (nx, ny) = contact.interact.normal();
+            if nx != 0.0 {
+                self.vel.x = 0.0;
+                self.direction = nx;
+            }
+            if ny != 0.0 {
+                self.vel.y = 0.0;
+            }
+            if ny < 0.0 {
+                self.ground = true;
+            }
+        }
+    }
+
+    /// 1 when facing right, -1 when facing left.
+    pub fn facing(&self) -> f64 {
+        self.direction
+    }
+
+    /// Where to draw the enemy, `alpha` of the way from where it was before
+    /// the last update to where it is now.
+    pub fn interpolated(&self, alpha: f64) -> Rect {
+        Rect {
+            x: self.previous.x + (self.rect.x - self.previous.x) * alpha,
+            y: self.previous.y + (self.rect.y - self.previous.y) * alpha,
+            ..self.rect.clone()
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use piston_window::*;
+
+use crate::animation::{Animator, Clip};
+use crate::assets::Registry;
+use crate::camera::Camera;
+use crate::campaign::Campaign;
+use crate::effects::Effects;
+use crate::hud::Hud;
+use crate::input::Input;
+use crate::level::Level;
+use crate::libs::{FileWatch, Rect};
+use crate::player::PlayerPhysics;
+use crate::replay::{Playback, Recorder, Tick};
+use crate::score::Score;
+use crate::sprite::{DrawParams, Sprite};
+use crate::world::{World, WorldEvent};
+use std::collections::BTreeMap;
+
+/// Seconds simulated by each update of the game, whatever the frame rate.
+const STEP: f64 = 1.0 / 60.0;
+/// Most seconds simulated at once, so a long hitch doesn't stall the game
+/// catching up.
+const MAX_CATCH_UP: f64 = 0.25;
+
+/// Everything loaded to play a level, shared by the states of the game.
+pub struct Game {
+    pub world: World,
+    pub camera: Camera,
+    pub input: Input,
+    effects: Effects,
+    hud: Hud,
+    /// Every sprite loaded, which worlds look theirs up in.
+    pub sprites: Registry<Sprite>,
+    /// Player animations, to make a new player from on restart.
+    clips: BTreeMap<String, Clip>,
+    /// Player physics file, reloaded when it changes.
+    pub physics: Option<FileWatch>,
+    pub recorder: Option<Recorder>,
+    pub playback: Option<Playback>,
+    pub campaign: Campaign,
+    /// Seconds of time passed that are not simulated yet.
+    accumulator: f64,
+    /// Whether the player asked to respawn since the last step.
+    pub respawn: bool,
+}
+
+impl Game {
+    pub fn new(
+        world: World,
+        camera: Camera,
+        input: Input,
+        hud: Hud,
+        sprites: Registry<Sprite>,
+        clips: BTreeMap<String, Clip>,
+    ) -> Game {
+        Game {
+            world,
+            camera,
+            input,
+            effects: Effects::default(),
+            hud,
+            sprites,
+            clips,
+            physics: None,
+            recorder: None,
+            playback: None,
+            campaign: Campaign::default(),
+            accumulator: 0.0,
+            respawn: false,
+        }
+    }
+
+    /// Starts the level over with a fresh world.
+    pub fn restart(&mut self) {
+        let level = self.world.level.clone();
+        self.play(level);
+        self.camera.reset(&self.world.player);
+    }
+
+    /// Moves on to `level`, shown by `camera` and `hud`, keeping the score
+    /// but not the time.
+    pub fn next_level(&mut self, level: Level, camera: Camera, hud: Hud) {
+        let score = Score {
+            time: 0.0,
+            ..self.world.score.clone()
+        };
+        self.play(level);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.replay.map = self.campaign.map().to_path_buf();
+        }
+        self.world.score = score;
+        self.camera = camera;
+        self.camera.focus(&self.world.player);
+        self.hud = hud;
+    }
+
+    /// Starts a fresh world in `level`.
+    fn play(&mut self, level: Level) {
+        let physics = self.world.player.physics.clone();
+        let animator = Animator::new(self.clips.clone(), "idle");
+        self.world = World::new(level, physics, animator, &self.sprites);
+        self.effects = Effects::default();
+        self.accumulator = 0.0;
+        self.respawn = false;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.start_over();
+        }
+        // the input replayed so far doesn't lead to a new world
+        if self.playback.take().is_some() {
+            eprintln!("replay stopped, it only covers the level it was recorded in");
+        }
+    }
+
+    /// Simulates `dt` seconds in fixed steps, returning what happened.
+    pub fn advance(&mut self, dt: f64) -> Vec<WorldEvent> {
+        let world = &mut self.world;
+        let camera = &mut self.camera;
+        if let Some(watch) = self.physics.as_mut() {
+            if watch.changed(dt) {
+                match PlayerPhysics::load(&watch.path) {
+                    Ok(physics) => world.player.physics = physics,
+                    Err(err) => eprintln!("warning: {}", err),
+                }
+            }
+        }
+
+        let mut happened = Vec::new();
+        self.accumulator += dt.min(MAX_CATCH_UP);
+        while self.accumulator >= STEP {
+            self.accumulator -= STEP;
+
+            // input only changes the game here, once per step, so
+            // replaying the same ticks plays the same game
+            let tick = match self.playback.as_mut() {
+                Some(playback) => match playback.next() {
+                    Some(tick) => tick,
+                    None => {
+                        eprintln!("replay finished");
+                        self.playback = None;
+                        Tick::new(&self.input.controller(), false)
+                    }
+                },
+                None => Tick::new(&self.input.controller(), self.respawn),
+            };
+            self.respawn = false;
+
+            let events = world.step(STEP, tick);
+            for event in events.iter() {
+                match event {
+                    WorldEvent::Bumped => {}
+                    WorldEvent::Broke { id } => {
+                        let object = &world.objects[*id];
+                        if let Some(sprite) = &object.sprite {
+                            self.effects.shatter(sprite, &object.rect);
+                        }
+                        camera.shake(0.2);
+                    }
+                    WorldEvent::Bounced { id } => self.effects.bounce(*id),
+                    WorldEvent::Collected { .. } => {}
+                    WorldEvent::Stomped { id } => {
+                        let enemy = &world.enemies[*id];
+                        if let Some(sprite) = &enemy.sprite {
+                            self.effects.knock_off(sprite, &enemy.rect);
+                        }
+                    }
+                    WorldEvent::Hurt => camera.shake(0.4),
+                    WorldEvent::Died => camera.shake(0.6),
+                    WorldEvent::Respawned => camera.focus(&world.player),
+                    WorldEvent::Cleared => {}
+                    WorldEvent::Pan {
+                        x,
+                        y,
+                        zoom,
+                        duration,
+                        hold,
+                    } => camera.pan_to(*x, *y, *zoom, *duration, *hold),
+                    WorldEvent::Zoom {
+                        zoom,
+                        duration,
+                        focus,
+                    } => camera.zoom_to(*zoom, *duration, Some(*focus)),
+                }
+            }
+            happened.extend(events);
+
+            self.effects.update(STEP, camera.max_h);
+
+            let player = &world.player;
+            let position = [player.rect.x, player.rect.y];
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.replay.record(tick, position);
+            }
+            if let Some(Err(tick)) = self.playback.as_ref().map(|p| p.check(position)) {
+                eprintln!(
+                    "warning: replay drifted from the recording at step {}",
+                    tick
+                );
+            }
+            camera.update(player, STEP);
+        }
+        happened
+    }
+
+    /// How far into the next step the time passed is, from 0 to 1, to draw
+    /// things that far from where they were to where they are.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / STEP
+    }
+
+    /// Draws the objects the camera sees, then enemies and the player, then
+    /// the HUD over them.
+    pub fn render(&self, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let world = &self.world;
+        let camera = &self.camera;
+        let effects = &self.effects;
+        let alpha = self.alpha();
+        let view = c.append_transform(camera.transform(alpha));
+
+        // items rise out from behind the block that let them out
+        for item in world.items.iter() {
+            if let Some(sprite) = &item.sprite {
+                sprite.draw(0, &item.rect, &DrawParams::default(), &view, g);
+            }
+        }
+        for (id, object) in world.objects.iter().enumerate() {
+            let rect = &object.rect;
+            if object.removed || !camera.sees([rect.x, rect.y, rect.scale, rect.scale]) {
+                continue;
+            }
+            if let Some(sprite) = &object.sprite {
+                let rect = Rect {
+                    y: rect.y - effects.offset(id),
+                    ..rect.clone()
+                };
+                sprite.draw(0, &rect, &object.draw, &view, g);
+            }
+        }
+        for enemy in world.enemies.iter().filter(|enemy| !enemy.defeated) {
+            if let Some(sprite) = &enemy.sprite {
+                let params = DrawParams {
+                    flip_x: enemy.facing() > 0.0,
+                    ..DrawParams::default()
+                };
+                sprite.draw(0, &enemy.interpolated(alpha), &params, &view, g);
+            }
+        }
+        for piece in effects.pieces.iter() {
+            let params = DrawParams {
+                rotation: piece.rotation,
+                ..DrawParams::default()
+            };
+            piece.sprite.draw(0, &piece.rect, &params, &view, g);
+        }
+
+        let player = &world.player;
+        if player.visible() {
+            if let Some(sprite) = &world.player_sprite {
+                let params = DrawParams {
+                    flip_x: player.facing() < 0.0,
+                    ..DrawParams::default()
+                };
+                sprite.draw(
+                    player.frame(),
+                    &player.interpolated(alpha),
+                    &params,
+                    &view,
+                    g,
+                );
+            }
+        }
+
+        let size = Size::from(c.get_view_size());
+        self.hud
+            .draw(&world.score, player.health, size, glyphs, c, g);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::camera::CameraSettings;
+    use crate::legend::Legend;
+    use crate::libs::Tilemap;
+    use std::path::Path;
+
+    /// A game of `map`, read with the game's legend, without a window.
+    pub fn game(map: &str) -> Game {
+        let legend = Legend::load(Path::new("assets/tiles.toml")).unwrap();
+        let level = Level::from_tilemap(&Tilemap::parse(map), &legend).unwrap();
+        let camera = Camera::new(CameraSettings::default(), 400.0, 400.0);
+        let animator = Animator::new(BTreeMap::new(), "idle");
+        let world = World::new(
+            level,
+            PlayerPhysics::default(),
+            animator,
+            &Registry::default(),
+        );
+        let input = Input::load(Path::new("assets/input.toml")).unwrap();
+        let hud = Hud::new("test".to_owned());
+        Game::new(
+            world,
+            camera,
+            input,
+            hud,
+            Registry::default(),
+            BTreeMap::new(),
+        )
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn uneven_frames_play_in_fixed_steps() {
+        let mut game = game("=====\n==P==\n=====\n11111");
+
+        // too short for a step, so it's all left over
+        game.advance(0.01);
+        assert_eq!(game.world.score.time, 0.0);
+        assert!(close(game.alpha(), 0.6));
+
+        game.advance(0.03);
+        assert!(close(game.world.score.time, 2.0 * STEP));
+        assert!(close(game.alpha(), 0.4));
+
+        game.advance(0.005);
+        assert!(close(game.world.score.time, 2.0 * STEP));
+        assert!(close(game.alpha(), 0.7));
+
+        // a long hitch only catches up so far
+        game.advance(1.0);
+        assert!(close(game.world.score.time, 17.0 * STEP));
+        assert!(close(game.alpha(), 0.7));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::libs::Rect;
+use crate::object::Object;
+
+/// Uniform grid of object ids keyed by tile coordinates, so collision only
+/// looks at objects near the player instead of every object in the level.
+pub struct Grid {
+    cell: f64,
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    pub fn new(cell: f64, width: usize, height: usize) -> Grid {
+        Grid {
+            cell,
+            width,
+            height,
+            cells: vec![Vec::new(); width * height],
+        }
+    }
+
+    /// Cells covering `[x0, y0, x1, y1]`, clamped to the grid.
+    fn cells_in(&self, bounds: [f64; 4]) -> Option<(usize, usize, usize, usize)> {
+        let [x0, y0, x1, y1] = bounds;
+        let col = |x: f64| (x / self.cell).floor();
+        let row = |y: f64| (y / self.cell).floor();
+        let (c0, r0, c1, r1) = (col(x0), row(y0), col(x1), row(y1));
+        if self.width == 0 || self.height == 0 || c1 < 0.0 || r1 < 0.0 {
+            return None;
+        }
+        if c0 >= self.width as f64 || r0 >= self.height as f64 {
+            return None;
+        }
+        Some((
+            c0.max(0.0) as usize,
+            r0.max(0.0) as usize,
+            (c1 as usize).min(self.width - 1),
+            (r1 as usize).min(self.height - 1),
+        ))
+    }
+
+    /// Adds object `id` in the cells its `rect` covers.
+    pub fn insert(&mut self, id: usize, rect: &Rect) {
+        let bounds = [rect.left(), rect.top(), rect.right(), rect.bottom()];
+        if let Some((c0, r0, c1, r1)) = self.cells_in(bounds) {
+            for row in r0..=r1 {
+                for col in c0..=c1 {
+                    self.cells[row * self.width + col].push(id);
+                }
+            }
+        }
+    }
+
+    /// Takes object `id` out of the cells its `rect` covers.
+    pub fn remove(&mut self, id: usize, rect: &Rect) {
+        let bounds = [rect.left(), rect.top(), rect.right(), rect.bottom()];
+        if let Some((c0, r0, c1, r1)) = self.cells_in(bounds) {
+            for row in r0..=r1 {
+                for col in c0..=c1 {
+                    self.cells[row * self.width + col].retain(|other| *other != id);
+                }
+            }
+        }
+    }
+
+    /// Ids of the objects in the cells touching `[x0, y0, x1, y1]`.
+    pub fn query(&self, bounds: [f64; 4]) -> Vec<usize> {
+        let mut ids = Vec::new();
+        if let Some((c0, r0, c1, r1)) = self.cells_in(bounds) {
+            for row in r0..=r1 {
+                for col in c0..=c1 {
+                    ids.extend_from_slice(&self.cells[row * self.width + col]);
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Solid objects a body at `rect` could touch moving by `dx`, `dy`, with
+    /// their ids, ready for the collider.
+    pub fn solids<'a>(
+        &self,
+        objects: &'a [Object],
+        rect: &Rect,
+        dx: f64,
+        dy: f64,
+    ) -> Vec<(usize, &'a Rect)> {
+        let bounds = [
+            rect.left() + dx.min(0.0),
+            rect.top() + dy.min(0.0),
+            rect.right() + dx.max(0.0),
+            rect.bottom() + dy.max(0.0),
+        ];
+        self.query(bounds)
+            .into_iter()
+            .filter(|i| objects[*i].solid)
+            .map(|i| (i, &objects[i].rect))
+            .collect()
+    }
+
+    /// Whether a solid object covers the point `x`, `y`.
+    pub fn solid_at(&self, objects: &[Object], x: f64, y: f64) -> bool {
+        self.query([x, y, x, y]).into_iter().any(|i| {
+            let object = &objects[i];
+            let rect = &object.rect;
+            object.solid
+                && x >= rect.left()
+                && x < rect.right()
+                && y >= rect.top()
+                && y < rect.bottom()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn tile(col: usize, row: usize) -> Rect {
+        Rect::new(col as f64 * 40.0, row as f64 * 40.0, 0.0, 0.0, 40.0)
+    }
+
+    #[test]
+    fn finds_only_nearby_objects() {
+        let mut grid = Grid::new(40.0, 10, 10);
+        grid.insert(0, &tile(1, 1));
+        grid.insert(1, &tile(8, 8));
+        assert_eq!(grid.query([30.0, 30.0, 70.0, 70.0]), vec![0]);
+        assert_eq!(grid.query([300.0, 300.0, 330.0, 330.0]), vec![1]);
+        assert!(grid.query([500.0, 500.0, 600.0, 600.0]).is_empty());
+    }
+
+    #[test]
+    fn reports_objects_spanning_cells_once() {
+        let mut grid = Grid::new(40.0, 10, 10);
+        grid.insert(0, &Rect::new(20.0, 20.0, 0.0, 0.0, 40.0));
+        assert_eq!(grid.query([0.0, 0.0, 120.0, 120.0]), vec![0]);
+    }
+
+    /// Compares a grid query against scanning every tile on full maps of
+    /// growing size. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_grid_against_linear_scan() {
+        for size in [32, 128, 512, 2048] {
+            let tiles: Vec<Rect> = (0..size * size).map(|i| tile(i % size, i / size)).collect();
+            let mut grid = Grid::new(40.0, size, size);
+            for (id, rect) in tiles.iter().enumerate() {
+                grid.insert(id, rect);
+            }
+            let bounds = [400.0, 400.0, 445.0, 450.0];
+            let runs = 1000;
+
+            let start = Instant::now();
+            let mut found = 0;
+            for _ in 0..runs {
+                found += tiles
+                    .iter()
+                    .filter(|r| r.right() >= bounds[0] && r.left() <= bounds[2])
+                    .filter(|r| r.bottom() >= bounds[1] && r.top() <= bounds[3])
+                    .count();
+            }
+            let linear = start.elapsed() / runs;
+
+            let start = Instant::now();
+            for _ in 0..runs {
+                found += grid.query(bounds).len();
+            }
+            let query = start.elapsed() / runs;
+
+            println!(
+                "{:>4}x{:<4} tiles: linear {:>10.2?}  grid {:>10.2?}  ({})",
+                size, size, linear, query, found
+            );
+        }
+    }
+}
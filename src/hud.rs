@@ -0,0 +1,107 @@
+use crate::player::MAX_HEALTH;
+use crate::score::Score;
+use piston_window::*;
+
+/// Text color of the HUD, and of the shadow that keeps it readable over the
+/// sky.
+const TEXT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const SHADOW: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+/// Where a label sits relative to the point it is drawn at.
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Score, coins, health, lives, deaths, time and level name, drawn over the
+/// world along the top of the window.
+pub struct Hud {
+    level: String,
+}
+
+impl Hud {
+    pub fn new(level: String) -> Hud {
+        Hud { level }
+    }
+
+    /// Draws `score` and the player's `health` for a `window` of that size,
+    /// scaling the text with it so it stays readable without covering the
+    /// game.
+    pub fn draw(
+        &self,
+        score: &Score,
+        health: u32,
+        window: Size,
+        glyphs: &mut Glyphs,
+        c: &Context,
+        g: &mut G2d,
+    ) {
+        let Size { width, height } = window;
+        let size = (height / 25.0).round().clamp(14.0, 32.0) as u32;
+        let margin = size as f64 * 0.75;
+        let top = margin + size as f64;
+        let second = top + size as f64 * 1.25;
+        let third = second + size as f64 * 1.25;
+
+        let mut label = |text: &str, x: f64, y: f64, align: Align| {
+            let text_width = glyphs.width(size, text).unwrap_or(0.0);
+            let x = match align {
+                Align::Left => x,
+                Align::Center => x - text_width / 2.0,
+                Align::Right => x - text_width,
+            };
+            for (color, offset) in [(SHADOW, 2.0), (TEXT, 0.0)].iter() {
+                text::Text::new_color(*color, size)
+                    .round()
+                    .draw(
+                        text,
+                        glyphs,
+                        &c.draw_state,
+                        c.transform.trans(x + offset, y + offset),
+                        g,
+                    )
+                    .unwrap();
+            }
+        };
+
+        label(
+            &format!("SCORE {:06}", score.points),
+            margin,
+            top,
+            Align::Left,
+        );
+        label(&self.level, width / 2.0, top, Align::Center);
+        let time = score.time as u32;
+        label(
+            &format!("TIME {}:{:02}", time / 60, time % 60),
+            width - margin,
+            top,
+            Align::Right,
+        );
+        label(
+            &format!("COINS {:02}", score.coins),
+            margin,
+            second,
+            Align::Left,
+        );
+        label(
+            &format!("HEALTH {}/{}", health, MAX_HEALTH),
+            width / 2.0,
+            second,
+            Align::Center,
+        );
+        label(
+            &format!("LIVES {}", score.lives),
+            width - margin,
+            second,
+            Align::Right,
+        );
+        label(
+            &format!("DEATHS {}", score.deaths),
+            width - margin,
+            third,
+            Align::Right,
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use crate::level::SettingsError;
+use crate::libs::Controller;
+use piston_window::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// Something the player can do, whatever it is bound to.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Pause,
+    Respawn,
+}
+
+/// A key, gamepad button or direction of a gamepad axis.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Binding {
+    Key {
+        key: Key,
+    },
+    Button {
+        button: u8,
+    },
+    /// Held while the axis is pushed past the dead zone towards `direction`,
+    /// 1 or -1.
+    Axis {
+        axis: u8,
+        direction: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputConfig {
+    #[serde(default = "default_dead_zone")]
+    dead_zone: f64,
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+fn default_dead_zone() -> f64 {
+    0.5
+}
+
+/// Turns Piston events into actions, through bindings read from a file.
+/// Buttons and axes of every gamepad count the same.
+pub struct Input {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+    dead_zone: f64,
+    keys: BTreeSet<Key>,
+    buttons: BTreeSet<u8>,
+    axes: BTreeMap<u8, f64>,
+    held: BTreeSet<Action>,
+}
+
+impl Input {
+    pub fn load(path: &Path) -> Result<Input, SettingsError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| SettingsError::Io(path.to_owned(), err))?;
+        let config: InputConfig =
+            toml::from_str(&content).map_err(|err| SettingsError::Parse(path.to_owned(), err))?;
+        Ok(Input {
+            bindings: config.bindings,
+            dead_zone: config.dead_zone,
+            keys: BTreeSet::new(),
+            buttons: BTreeSet::new(),
+            axes: BTreeMap::new(),
+            held: BTreeSet::new(),
+        })
+    }
+
+    fn bound(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key { key } => self.keys.contains(key),
+            Binding::Button { button } => self.buttons.contains(button),
+            Binding::Axis { axis, direction } => self
+                .axes
+                .get(axis)
+                .is_some_and(|position| position * direction >= self.dead_zone),
+        }
+    }
+
+    /// Updates what is held from `e`, returning the actions it started.
+    pub fn event(&mut self, e: &Event) -> Vec<Action> {
+        if let Some(args) = e.button_args() {
+            let pressed = args.state == ButtonState::Press;
+            match args.button {
+                Button::Keyboard(key) if pressed => {
+                    self.keys.insert(key);
+                }
+                Button::Keyboard(key) => {
+                    self.keys.remove(&key);
+                }
+                Button::Controller(button) if pressed => {
+                    self.buttons.insert(button.button);
+                }
+                Button::Controller(button) => {
+                    self.buttons.remove(&button.button);
+                }
+                _ => return Vec::new(),
+            }
+        } else if let Some(args) = e.controller_axis_args() {
+            self.axes.insert(args.axis, args.position);
+        } else {
+            return Vec::new();
+        }
+
+        let held: BTreeSet<Action> = self
+            .bindings
+            .iter()
+            .filter(|(_, bindings)| bindings.iter().any(|binding| self.bound(binding)))
+            .map(|(action, _)| *action)
+            .collect();
+        let started = held.difference(&self.held).copied().collect();
+        self.held = held;
+        started
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    /// Movement of the player for the actions held.
+    pub fn controller(&self) -> Controller {
+        Controller {
+            up: self.held(Action::Jump),
+            left: self.held(Action::MoveLeft),
+            right: self.held(Action::MoveRight),
+        }
+    }
+}
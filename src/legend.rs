@@ -0,0 +1,161 @@
+use crate::sprite::DrawParams;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What a tile does besides being drawn and collided with.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileBehavior {
+    #[default]
+    None,
+    Spawn,
+    Checkpoint(String),
+    /// An item of the given kind to pick up, e.g. `coin`.
+    Collectible(String),
+    /// Where an enemy starts.
+    Enemy(EnemyKind),
+    /// Ends the level once the player reaches it.
+    Goal,
+}
+
+/// How an enemy moves. Its sprite is named after it.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnemyKind {
+    /// Walks along the ground, turning at walls and ledges.
+    Walker,
+    /// Hops forward every so often, turning at walls.
+    Hopper,
+    /// Flies back and forth between where it starts and `dx`, `dy` tiles
+    /// away, through anything.
+    Flyer { dx: f64, dy: f64 },
+}
+
+impl EnemyKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyKind::Walker => "walker",
+            EnemyKind::Hopper => "hopper",
+            EnemyKind::Flyer { .. } => "flyer",
+        }
+    }
+}
+
+/// What a tile does when the player hits it from below.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BumpBehavior {
+    #[default]
+    None,
+    /// Breaks into debris, leaving nothing behind.
+    Break,
+    /// Bounces, lets the item `spawn` out on top and becomes the `used`
+    /// sprite, which does nothing when bumped again.
+    Item { spawn: String, used: String },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileKind {
+    pub sprite: Option<String>,
+    #[serde(default)]
+    pub solid: bool,
+    #[serde(default)]
+    pub behavior: TileBehavior,
+    #[serde(default)]
+    pub bump: BumpBehavior,
+    #[serde(default)]
+    pub draw: DrawParams,
+}
+
+/// Maps the characters of an ASCII tilemap to tile kinds.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Legend {
+    tiles: BTreeMap<char, TileKind>,
+}
+
+impl Legend {
+    pub fn load(path: &Path) -> Result<Legend, LegendError> {
+        let content = fs::read_to_string(path).map_err(LegendError::Io)?;
+        toml::from_str(&content).map_err(LegendError::Parse)
+    }
+
+    pub fn get(&self, tile: char) -> Option<&TileKind> {
+        self.tiles.get(&tile)
+    }
+}
+
+#[derive(Debug)]
+pub enum LegendError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for LegendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LegendError::Io(err) => write!(f, "can't read legend: {}", err),
+            LegendError::Parse(err) => write!(f, "invalid legend: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_legend_of_the_game_parses() {
+        let legend = Legend::load(Path::new("assets/tiles.toml")).unwrap();
+        let brick = legend.get('2').unwrap();
+        assert_eq!(brick.sprite.as_deref(), Some("brick"));
+        assert!(brick.solid);
+        assert_eq!(brick.bump, BumpBehavior::Break);
+        assert!(matches!(
+            legend.get('P').unwrap().behavior,
+            TileBehavior::Spawn
+        ));
+        assert!(legend.get('#').is_none());
+    }
+
+    #[test]
+    fn behaviors_take_their_arguments() {
+        let legend: Legend = toml::from_str(
+            r#"
+            [tiles.a]
+            behavior = { checkpoint = "a" }
+            [tiles.f]
+            behavior = { enemy = { flyer = { dx = 4, dy = -1 } } }
+            [tiles."?"]
+            solid = true
+            bump = { item = { spawn = "coin", used = "used_block" } }
+            "#,
+        )
+        .unwrap();
+        let checkpoint = &legend.get('a').unwrap().behavior;
+        assert!(matches!(checkpoint, TileBehavior::Checkpoint(name) if name == "a"));
+        let flyer = &legend.get('f').unwrap().behavior;
+        let kind = EnemyKind::Flyer { dx: 4.0, dy: -1.0 };
+        assert!(matches!(flyer, TileBehavior::Enemy(enemy) if *enemy == kind));
+        let block = legend.get('?').unwrap();
+        assert!(block.sprite.is_none());
+        assert_eq!(
+            block.bump,
+            BumpBehavior::Item {
+                spawn: "coin".to_owned(),
+                used: "used_block".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let err = toml::from_str::<Legend>("[tiles.x]\nsolidd = true\n").unwrap_err();
+        assert!(err.to_string().contains("solidd"), "{}", err);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::camera::CameraSettings;
+use crate::legend::{BumpBehavior, EnemyKind, Legend, TileBehavior};
+use crate::libs::Tilemap;
+use crate::spawn::{SpawnCollector, SpawnError, SpawnPoint, Spawns};
+use crate::sprite::DrawParams;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A possibly drawn, possibly solid tile placed in a level.
+#[derive(Clone)]
+pub struct Tile {
+    pub row: usize,
+    pub col: usize,
+    /// `None` for a tile that is only collided with, like an invisible wall.
+    pub sprite: Option<String>,
+    pub solid: bool,
+    pub draw: DrawParams,
+    pub bump: BumpBehavior,
+}
+
+/// An item placed in a level for the player to pick up by touching it.
+#[derive(Clone)]
+pub struct Collectible {
+    pub row: usize,
+    pub col: usize,
+    /// Sprite of the item, and what the game makes of picking it up.
+    pub kind: String,
+}
+
+/// Where an enemy starts in a level, and how it moves.
+#[derive(Clone)]
+pub struct EnemySpawn {
+    pub row: usize,
+    pub col: usize,
+    pub kind: EnemyKind,
+}
+
+/// An image cut into tiles, whose sprites are named `<name>:<index>`.
+#[derive(Clone)]
+pub struct TilesetImage {
+    pub name: String,
+    pub image: PathBuf,
+    pub tile_width: f64,
+    pub tile_height: f64,
+    pub margin: f64,
+    pub spacing: f64,
+}
+
+/// What happens when the player enters a trigger.
+#[derive(Clone)]
+pub enum TriggerAction {
+    Checkpoint(String),
+    /// Shows the camera `x`, `y`, in tiles, for `hold` seconds, easing there
+    /// and back in `duration` seconds. Happens once.
+    Pan {
+        x: f64,
+        y: f64,
+        zoom: Option<f64>,
+        duration: f64,
+        hold: f64,
+    },
+    /// Eases the camera zoom to `zoom` around the player.
+    Zoom {
+        zoom: f64,
+        duration: f64,
+    },
+    /// Ends the level.
+    Goal,
+}
+
+/// An area of the level, in tiles, that reacts to the player.
+#[derive(Clone)]
+pub struct Trigger {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    pub action: TriggerAction,
+}
+
+impl Trigger {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+}
+
+/// Everything a map describes, independent of the format it was read from.
+#[derive(Clone)]
+pub struct Level {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Tile>,
+    pub collectibles: Vec<Collectible>,
+    pub enemies: Vec<EnemySpawn>,
+    pub spawns: Spawns,
+    pub triggers: Vec<Trigger>,
+    pub tilesets: Vec<TilesetImage>,
+    pub warnings: Vec<String>,
+}
+
+impl Level {
+    pub fn from_tilemap(tilemap: &Tilemap, legend: &Legend) -> Result<Level, SpawnError> {
+        let mut tiles = Vec::new();
+        let mut collectibles = Vec::new();
+        let mut enemies = Vec::new();
+        let mut spawns = SpawnCollector::default();
+        let mut triggers = Vec::new();
+        let mut warnings = Vec::new();
+
+        for (row, chars) in tilemap.rows().iter().enumerate() {
+            for (col, c) in chars.iter().enumerate() {
+                let kind = match legend.get(*c) {
+                    Some(kind) => kind,
+                    None => {
+                        warnings.push(format!(
+                            "row {}, col {}: unknown tile '{}'",
+                            row + 1,
+                            col + 1,
+                            c
+                        ));
+                        continue;
+                    }
+                };
+
+                let point = SpawnPoint { row, col };
+                match &kind.behavior {
+                    TileBehavior::None => {}
+                    TileBehavior::Spawn => spawns.player(point)?,
+                    TileBehavior::Checkpoint(name) => {
+                        spawns.checkpoint(name, point)?;
+                        triggers.push(Trigger {
+                            x: col as f64,
+                            y: row as f64,
+                            w: 1.0,
+                            h: 1.0,
+                            action: TriggerAction::Checkpoint(name.clone()),
+                        });
+                    }
+                    TileBehavior::Collectible(kind) => collectibles.push(Collectible {
+                        row,
+                        col,
+                        kind: kind.clone(),
+                    }),
+                    TileBehavior::Enemy(kind) => enemies.push(EnemySpawn {
+                        row,
+                        col,
+                        kind: kind.clone(),
+                    }),
+                    TileBehavior::Goal => triggers.push(Trigger {
+                        x: col as f64,
+                        y: row as f64,
+                        w: 1.0,
+                        h: 1.0,
+                        action: TriggerAction::Goal,
+                    }),
+                }
+
+                if kind.sprite.is_some() || kind.solid {
+                    tiles.push(Tile {
+                        row,
+                        col,
+                        sprite: kind.sprite.clone(),
+                        solid: kind.solid,
+                        draw: kind.draw,
+                        bump: kind.bump.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(Level {
+            width: tilemap.width(),
+            height: tilemap.height(),
+            tiles,
+            collectibles,
+            enemies,
+            spawns: spawns.finish()?,
+            triggers,
+            tilesets: Vec::new(),
+            warnings,
+        })
+    }
+}
+
+/// Tuning of a level, read from the `.toml` file next to its map.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelSettings {
+    /// Shown on the HUD; the map's file name if left out.
+    pub name: Option<String>,
+    pub camera: CameraSettings,
+}
+
+impl LevelSettings {
+    /// Settings for the map at `map`, or the defaults if it has none.
+    pub fn load(map: &Path) -> Result<LevelSettings, SettingsError> {
+        let path = map.with_extension("toml");
+        if !path.exists() {
+            return Ok(LevelSettings::default());
+        }
+        let content =
+            fs::read_to_string(&path).map_err(|err| SettingsError::Io(path.clone(), err))?;
+        toml::from_str(&content).map_err(|err| SettingsError::Parse(path, err))
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            SettingsError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legend() -> Legend {
+        toml::from_str(
+            r#"
+            [tiles."="]
+            [tiles."1"]
+            sprite = "ground"
+            solid = true
+            [tiles."|"]
+            solid = true
+            [tiles.P]
+            behavior = "spawn"
+            [tiles.o]
+            behavior = { collectible = "coin" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn level(map: &str) -> Level {
+        Level::from_tilemap(&Tilemap::parse(map), &legend()).unwrap()
+    }
+
+    #[test]
+    fn tiles_are_placed_by_row_and_column() {
+        let level = level("=P=o\n1111\n");
+        assert_eq!((level.width, level.height), (4, 2));
+        assert_eq!(level.tiles.len(), 4);
+        assert!(level
+            .tiles
+            .iter()
+            .all(|tile| tile.row == 1 && tile.solid && tile.sprite.as_deref() == Some("ground")));
+        assert_eq!(level.collectibles.len(), 1);
+        assert_eq!(level.collectibles[0].col, 3);
+        assert!(level.warnings.is_empty());
+    }
+
+    #[test]
+    fn solid_tiles_without_a_sprite_are_invisible_walls() {
+        let level = level("|P|\n111\n");
+        let walls: Vec<_> = level
+            .tiles
+            .iter()
+            .filter(|tile| tile.sprite.is_none())
+            .collect();
+        assert_eq!(walls.len(), 2);
+        assert!(walls.iter().all(|tile| tile.solid && tile.row == 0));
+        // markers and empty tiles place nothing
+        assert_eq!(level.tiles.len(), 5);
+    }
+
+    #[test]
+    fn unknown_tiles_are_warned_about_counting_from_one() {
+        let level = level("P=\n=#\n");
+        assert_eq!(level.warnings, ["row 2, col 2: unknown tile '#'"]);
+        assert!(level.tiles.is_empty());
+    }
+}
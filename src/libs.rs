@@ -1,5 +1,6 @@
-use std::fs::File;
-use std::io::prelude::Read;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 pub struct Vec2d {
 	pub x: f64,
@@ -32,11 +33,11 @@ pub struct Rect{
 impl Rect {
 	pub fn new(x: f64, y: f64, w: f64, h: f64, scale: f64)->Rect{
 		Rect{
-			x: x,
-			y: y,
-			w: w,
-			h: h,
-			scale: scale
+			x,
+			y,
+			w,
+			h,
+			scale
 		}
 	}
 
@@ -65,17 +66,15 @@ impl Rect {
 
 }
 
-pub struct Tilemap();
+pub struct Tilemap {
+	tiles: Vec<Vec<char>>,
+}
 
 impl Tilemap {
 
-	pub fn new(path: &str)->Vec<Vec<char>>{
-		
-		let mut file = File::open(path).expect("can't open file");
-		let mut content = String::new();
-		file.read_to_string(&mut content).expect("can't read file");
-
-		let mut map = Vec::new();
+	/// A tilemap with one row per line of `content`.
+	pub fn parse(content: &str) -> Tilemap {
+		let mut tiles = Vec::new();
 
 		for lines in content.lines(){
 			let mut row = Vec::new();
@@ -83,16 +82,72 @@ impl Tilemap {
 				row.push(c);
 			}
 
-			map.push(row);
+			tiles.push(row);
 		}
 
-		map
+		Tilemap { tiles }
+	}
+
+	pub fn rows(&self) -> &[Vec<char>] {
+		&self.tiles
+	}
+
+	pub fn width(&self) -> usize {
+		self.tiles.first().map_or(0, |row| row.len())
+	}
+
+	pub fn height(&self) -> usize {
+		self.tiles.len()
 	}
 
 }
 
+#[derive(Default)]
 pub struct Controller {
 	pub up: bool,
 	pub left: bool,
 	pub right: bool
+}
+
+/// Tells when a file changed on disk, checking at most every `interval`
+/// seconds.
+pub struct FileWatch {
+	pub path: PathBuf,
+	interval: f64,
+	elapsed: f64,
+	modified: Option<SystemTime>,
+}
+
+impl FileWatch {
+
+	pub fn new(path: PathBuf, interval: f64) -> FileWatch {
+		let modified = FileWatch::modified(&path);
+		FileWatch{
+			path,
+			interval,
+			elapsed: 0.0,
+			modified,
+		}
+	}
+
+	fn modified(path: &Path) -> Option<SystemTime> {
+		fs::metadata(path).and_then(|meta| meta.modified()).ok()
+	}
+
+	/// Whether the file changed since the last time this returned true,
+	/// `dt` seconds after the previous call.
+	pub fn changed(&mut self, dt: f64) -> bool {
+		self.elapsed += dt;
+		if self.elapsed < self.interval {
+			return false;
+		}
+		self.elapsed = 0.0;
+		let modified = FileWatch::modified(&self.path);
+		if modified == self.modified {
+			return false;
+		}
+		self.modified = modified;
+		modified.is_some()
+	}
+
 }
\ No newline at end of file
@@ -0,0 +1,368 @@
+use crate::animation::AnimationSet;
+use crate::assets::Manifest;
+use crate::camera::CameraSettings;
+use crate::legend::Legend;
+use crate::level::{Level, LevelSettings};
+use crate::libs::Tilemap;
+use crate::tiled;
+use image::RgbaImage;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// What the sprites cut from an image are called.
+#[derive(Debug)]
+pub enum Names {
+    /// A name for each tile, in order; tiles past the last name are skipped.
+    Each(Vec<String>),
+    /// A single sprite with every tile as a frame.
+    Frames(String),
+    /// `<name>:<index>` for each tile.
+    Numbered(String),
+}
+
+/// An image to cut into equally sized tiles, like `Tileset::slice` does.
+#[derive(Debug)]
+pub struct ImageAsset {
+    /// Where the image was asked for, to blame when it can't be loaded.
+    pub source: String,
+    pub path: PathBuf,
+    pub tile_width: f64,
+    pub tile_height: f64,
+    pub margin: f64,
+    pub spacing: f64,
+    pub names: Names,
+}
+
+/// The level and what it needs, parsed before any image is read.
+pub struct LevelData {
+    pub map: PathBuf,
+    pub level: Level,
+    pub name: String,
+    pub camera: CameraSettings,
+    /// Player animations, only loaded along with the rest of the game.
+    pub animations: Option<AnimationSet>,
+}
+
+/// What the loading thread sends back, in this order.
+pub enum Loaded {
+    /// The level is parsed, which took `bytes` of the `total` bytes to load.
+    Level {
+        data: Box<LevelData>,
+        bytes: u64,
+        total: u64,
+    },
+    /// An image is decoded, and only needs uploading to the GPU.
+    Image {
+        asset: ImageAsset,
+        image: RgbaImage,
+        bytes: u64,
+    },
+    Failed(String),
+}
+
+/// Reads and decodes assets on a thread of its own, so the window keeps
+/// drawing while they load.
+pub struct Loader {
+    receiver: Receiver<Loaded>,
+    /// Bytes loaded so far, out of `total`, once the level says how many.
+    pub done: u64,
+    pub total: u64,
+}
+
+impl Loader {
+    /// Loads `map` along with everything else the game needs.
+    pub fn new(assets: PathBuf, manifest: Manifest, map: PathBuf) -> Loader {
+        Loader::spawn(assets, Some(manifest), map)
+    }
+
+    /// Loads only `map` and its own images, to play after another level.
+    pub fn level(assets: PathBuf, map: PathBuf) -> Loader {
+        Loader::spawn(assets, None, map)
+    }
+
+    fn spawn(assets: PathBuf, manifest: Option<Manifest>, map: PathBuf) -> Loader {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            if let Err(err) = load(&assets, manifest.as_ref(), &map, &sender) {
+                // the scene is gone if sending fails, so there's no one to tell
+                let _ = sender.send(Loaded::Failed(err));
+            }
+        });
+        Loader {
+            receiver,
+            done: 0,
+            total: 0,
+        }
+    }
+
+    /// What has loaded since the last call, without waiting. `None` once
+    /// everything has.
+    pub fn poll(&mut self) -> Option<Vec<Loaded>> {
+        let mut loaded = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(item) => {
+                    match &item {
+                        Loaded::Level { bytes, total, .. } => {
+                            self.done += bytes;
+                            self.total = *total;
+                        }
+                        Loaded::Image { bytes, .. } => self.done += bytes,
+                        Loaded::Failed(_) => {}
+                    }
+                    loaded.push(item);
+                }
+                Err(TryRecvError::Empty) => return Some(loaded),
+                Err(TryRecvError::Disconnected) if loaded.is_empty() => return None,
+                Err(TryRecvError::Disconnected) => return Some(loaded),
+            }
+        }
+    }
+
+    /// How much has loaded, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+}
+
+/// Size of the file at `path` in bytes, or 0 if it can't be read, which
+/// reading it will report.
+fn size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+/// Parses the level, then decodes the images it needs one by one, along
+/// with those of the `manifest` and the player if given.
+fn load(
+    assets: &Path,
+    manifest: Option<&Manifest>,
+    map: &Path,
+    sender: &Sender<Loaded>,
+) -> Result<(), String> {
+    let mut files = vec![map.to_path_buf()];
+    let level = if tiled::is_tiled_map(map) {
+        tiled::load(map).map_err(|err| err.to_string())?
+    } else {
+        let content =
+            fs::read_to_string(map).map_err(|err| format!("{}: {}", map.display(), err))?;
+        let legend_path = assets.join("tiles.toml");
+        let legend = Legend::load(&legend_path).map_err(|err| format!("tiles.toml: {}", err))?;
+        files.push(legend_path);
+        Level::from_tilemap(&Tilemap::parse(&content), &legend)
+            .map_err(|err| format!("{}: {}", map.display(), err))?
+    };
+    let settings = LevelSettings::load(map).map_err(|err| err.to_string())?;
+    files.push(map.with_extension("toml"));
+    let name = settings.name.unwrap_or_else(|| {
+        let stem = map.file_stem().unwrap_or_default();
+        stem.to_string_lossy().into_owned()
+    });
+
+    let mut images = Vec::new();
+    let mut animations = None;
+    if let Some(manifest) = manifest {
+        for (name, entry) in manifest.textures.iter() {
+            let names = entry.sprites.clone().unwrap_or_else(|| vec![name.clone()]);
+            images.push(ImageAsset {
+                source: manifest.describe(&format!("texture '{}'", name)),
+                path: manifest.path(&entry.path),
+                tile_width: entry.tile[0],
+                tile_height: entry.tile[1],
+                margin: entry.margin,
+                spacing: entry.spacing,
+                names: Names::Each(names),
+            });
+        }
+
+        let path = assets.join("player_animations.toml");
+        let set =
+            AnimationSet::load(&path).map_err(|err| format!("player_animations.toml: {}", err))?;
+        files.push(path);
+        images.push(ImageAsset {
+            source: "player_animations.toml: sheet".to_owned(),
+            path: assets.join(&set.sheet),
+            tile_width: set.frame_width,
+            tile_height: set.frame_height,
+            margin: set.margin,
+            spacing: set.spacing,
+            names: Names::Frames("player".to_owned()),
+        });
+        animations = Some(set);
+    }
+    for source in level.tilesets.iter() {
+        images.push(ImageAsset {
+            source: format!("{}: tileset '{}'", map.display(), source.name),
+            path: source.image.clone(),
+            tile_width: source.tile_width,
+            tile_height: source.tile_height,
+            margin: source.margin,
+            spacing: source.spacing,
+            names: Names::Numbered(source.name.clone()),
+        });
+    }
+
+    // an image can't be cut into tiles of no size
+    let empty = |asset: &&ImageAsset| !(asset.tile_width > 0.0 && asset.tile_height > 0.0);
+    if let Some(asset) = images.iter().find(empty) {
+        return Err(format!(
+            "{}: tile size must be above 0, not {}x{}",
+            asset.source, asset.tile_width, asset.tile_height
+        ));
+    }
+
+    let bytes: u64 = files.iter().map(|path| size(path)).sum();
+    let total = bytes + images.iter().map(|asset| size(&asset.path)).sum::<u64>();
+    let data = LevelData {
+        map: map.to_path_buf(),
+        level,
+        name,
+        camera: settings.camera,
+        animations,
+    };
+    let level = Loaded::Level {
+        data: Box::new(data),
+        bytes,
+        total,
+    };
+    if sender.send(level).is_err() {
+        return Ok(());
+    }
+
+    for asset in images {
+        let describe = |err: &dyn fmt::Display| {
+            format!(
+                "{}: can't read '{}': {}",
+                asset.source,
+                asset.path.display(),
+                err
+            )
+        };
+        let content = fs::read(&asset.path).map_err(|err| describe(&err))?;
+        let image = image::load_from_memory(&content)
+            .map_err(|err| describe(&err))?
+            .to_rgba8();
+        let bytes = content.len() as u64;
+        if sender
+            .send(Loaded::Image {
+                asset,
+                image,
+                bytes,
+            })
+            .is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::TextureEntry;
+    use std::collections::BTreeMap;
+
+    /// Runs `loader` to the end, returning everything it loaded.
+    fn finish(loader: &mut Loader) -> Vec<Loaded> {
+        let mut loaded = Vec::new();
+        while let Some(items) = loader.poll() {
+            loaded.extend(items);
+        }
+        loaded
+    }
+
+    fn manifest() -> Manifest {
+        Manifest::load(Path::new("assets/assets.toml")).unwrap()
+    }
+
+    fn loader(manifest: Manifest, map: &str) -> Loader {
+        Loader::new(PathBuf::from("assets"), manifest, PathBuf::from(map))
+    }
+
+    #[test]
+    fn the_level_comes_first_and_every_byte_is_counted() {
+        let manifest = manifest();
+        let textures = manifest.textures.len();
+        let mut loader = loader(manifest, "assets/map.txt");
+        let loaded = finish(&mut loader);
+
+        assert!(matches!(loaded.first(), Some(Loaded::Level { .. })));
+        let images = loaded
+            .iter()
+            .filter(|item| matches!(item, Loaded::Image { .. }))
+            .count();
+        // every texture of the manifest, and the player sheet
+        assert_eq!(images, textures + 1);
+        assert!(loader.total > 0);
+        assert_eq!(loader.done, loader.total);
+    }
+
+    #[test]
+    fn another_level_loads_without_the_rest_of_the_game() {
+        let mut loader = Loader::level(PathBuf::from("assets"), PathBuf::from("assets/map2.txt"));
+        let loaded = finish(&mut loader);
+        assert_eq!(loaded.len(), 1);
+        match &loaded[0] {
+            Loaded::Level { data, .. } => {
+                assert_eq!(data.name, "World 1-2");
+                assert!(data.animations.is_none());
+            }
+            _ => panic!("map2.txt didn't load"),
+        }
+        assert_eq!(loader.done, loader.total);
+    }
+
+    #[test]
+    fn a_missing_map_fails_without_images() {
+        let loaded = finish(&mut loader(manifest(), "assets/nowhere.txt"));
+        assert_eq!(loaded.len(), 1);
+        assert!(matches!(&loaded[0], Loaded::Failed(err) if err.contains("nowhere.txt")));
+    }
+
+    #[test]
+    fn textures_need_a_tile_size() {
+        let mut manifest = manifest();
+        manifest.textures.get_mut("coin").unwrap().tile = [80.0, 0.0];
+        let loaded = finish(&mut loader(manifest, "assets/map.txt"));
+        assert_eq!(loaded.len(), 1);
+        match &loaded[0] {
+            Loaded::Failed(err) => assert_eq!(
+                err,
+                "assets/assets.toml: texture 'coin': tile size must be above 0, not 80x0"
+            ),
+            _ => panic!("a texture without a tile size loaded"),
+        }
+    }
+
+    #[test]
+    fn a_missing_texture_names_its_manifest_entry() {
+        let mut manifest = manifest();
+        manifest.textures = BTreeMap::new();
+        manifest.textures.insert(
+            "star".to_owned(),
+            TextureEntry {
+                path: "star.png".to_owned(),
+                tile: [80.0, 80.0],
+                margin: 0.0,
+                spacing: 0.0,
+                sprites: None,
+            },
+        );
+        let loaded = finish(&mut loader(manifest, "assets/map.txt"));
+        match loaded.last() {
+            Some(Loaded::Failed(err)) => assert!(
+                err.starts_with("assets/assets.toml: texture 'star': can't read 'assets/star.png'"),
+                "{}",
+                err
+            ),
+            _ => panic!("loading a missing texture didn't fail"),
+        }
+    }
+}
@@ -1,14 +1,36 @@
-use find_folder;
 use fps_counter::FPSCounter;
 use piston_window::*;
+mod animation;
+mod assets;
 mod camera;
+mod campaign;
 mod collider;
+mod effects;
+mod enemy;
+mod game;
+mod grid;
+mod hud;
+mod input;
+mod legend;
+mod level;
 mod libs;
+mod loader;
 mod object;
 mod player;
+mod replay;
+mod score;
 mod scene;
+mod spawn;
 mod sprite;
+mod state;
+mod tiled;
+mod world;
+use assets::Manifest;
+use campaign::Campaign;
+use replay::{Replay, Session};
 use scene::Scene;
+use std::env;
+use std::path::PathBuf;
 
 fn main() {
     let mut window: PistonWindow = WindowSettings::new("2D Platformer", (600, 600))
@@ -19,25 +41,59 @@ fn main() {
     let mut fps_counter = FPSCounter::new();
 
     let assets = find_folder::Search::Kids(1).for_folder("assets").unwrap();
-    let mut glyphs = window
-        .load_font(assets.join("FiraSans-Regular.ttf"))
-        .unwrap();
-    let mut scene = Scene::new(assets);
+    let manifest =
+        Manifest::load(&assets.join("assets.toml")).unwrap_or_else(|err| panic!("{}", err));
+    let font = manifest.font("ui").unwrap_or_else(|err| panic!("{}", err));
+    let mut glyphs = window.load_font(font).unwrap();
+    // [map] [--record <file> | --replay <file>]
+    let mut map = None;
+    let mut session = Session::Live;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => {
+                let path = PathBuf::from(args.next().expect("--record needs a file"));
+                session = Session::Record(path);
+            }
+            "--replay" => {
+                let path = PathBuf::from(args.next().expect("--replay needs a file"));
+                let replay = Replay::load(&path).unwrap_or_else(|err| panic!("{}", err));
+                session = Session::Replay(replay);
+            }
+            _ => map = Some(PathBuf::from(arg)),
+        }
+    }
+    let stages = manifest.stages().unwrap_or_else(|err| panic!("{}", err));
+    let save = campaign::save_path();
+    if save.is_none() {
+        eprintln!("warning: no home directory to save progress in");
+    }
+    let mut campaign = Campaign::new(stages, save);
+    let map = match &session {
+        Session::Replay(replay) => Some(replay.map.clone()),
+        _ => map,
+    };
+    if let Some(map) = map {
+        campaign.select(&map);
+    }
+    let mut scene = Scene::new(assets, manifest, campaign, session);
 
     let mut fps = String::default();
 
     while let Some(e) = window.next() {
         scene.update(&e, &mut window, &mut glyphs);
+        let height = window.size().height;
         window.draw_2d(&e, |c, g, device| {
-            let transform = c.transform.trans(10.0, 25.0);
+            // bottom left, out of the way of the HUD
+            let transform = c.transform.trans(10.0, height - 10.0);
             text::Text::new_color([0.0, 0.0, 0.0, 1.0], 24)
                 .draw(&fps, &mut glyphs, &c.draw_state, transform, g)
                 .unwrap();
 
             glyphs.factory.encoder.flush(device);
         });
-        if let Some(_) = &e.update_args() {
-            fps = format!("{} fps", fps_counter.tick().to_string());
+        if e.render_args().is_some() {
+            fps = format!("{} fps", fps_counter.tick());
         }
     }
 }
@@ -1,35 +1,39 @@
-use crate::sprite::{Sprite, SpriteEvent};
+use crate::assets::Handle;
+use crate::legend::BumpBehavior;
+use crate::sprite::{DrawParams, Sprite};
 use crate::libs::Rect;
-use piston_window::*;
 
+/// Sprites of what bumping an object makes: the one it turns into, and the
+/// one of the item it lets out.
+#[derive(Clone, Default)]
+pub struct BumpSprites {
+	pub used: Option<Handle<Sprite>>,
+	pub item: Option<Handle<Sprite>>,
+}
+
+/// A tile placed in the world, drawn with `sprite` unless it has none.
 #[derive(Clone)]
 pub struct Object {
-	sprite: Sprite,
+	pub sprite: Option<Handle<Sprite>>,
 	pub solid: bool,
 	pub rect: Rect,
+	pub draw: DrawParams,
+	pub bump: BumpBehavior,
+	pub bump_sprites: BumpSprites,
+	/// Whether it was destroyed, and is no longer drawn or collided with.
+	pub removed: bool,
 }
 
 impl Object {
-	pub fn new(sprite: Sprite, rect: Rect, solid: bool) -> Object {
+	pub fn new(sprite: Option<Handle<Sprite>>, rect: Rect, solid: bool, draw: DrawParams, bump: BumpBehavior) -> Object {
 		Object {
-			sprite: sprite,
-			solid: solid,
-			rect: rect,
+			sprite,
+			solid,
+			rect,
+			draw,
+			bump,
+			bump_sprites: BumpSprites::default(),
+			removed: false,
 		}
 	}
-}
-
-impl SpriteEvent for Object {
-	fn render(&mut self, e: &Event, w: &mut PistonWindow){
-		let texture = self.sprite.get_texture(0);
-		let rect = &self.rect;
-		w.draw_2d(e, |c,g,_d| {
-			image(
-				texture, 
-				c.trans(rect.x, rect.y)
-				.scale(rect.scale / texture.get_width() as f64, rect.scale / texture.get_height() as f64)
-				.transform, g
-			);
-		});
-	}
 }
\ No newline at end of file
@@ -1,134 +1,312 @@
+use crate::animation::Animator;
 use crate::collider::{Collider, Interact};
+use crate::grid::Grid;
+use crate::level::SettingsError;
 use crate::libs::{Controller, Rect, Vec2d};
 use crate::object::Object;
-use crate::sprite::{Sprite, SpriteEvent};
-use piston_window::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// How the player moves, in pixels and seconds.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerPhysics {
+    /// Downwards acceleration.
+    pub gravity: f64,
+    /// Acceleration while running.
+    pub run_acceleration: f64,
+    /// How fast running speed fades, per second.
+    pub friction: f64,
+    /// Upwards speed at the start of a jump.
+    pub jump_speed: f64,
+    /// Part of the upwards speed kept when the jump is released early.
+    pub jump_cut: f64,
+    /// Seconds after walking off a ledge the player can still jump.
+    pub coyote_time: f64,
+    /// Seconds a jump pressed in the air is remembered until landing.
+    pub jump_buffer: f64,
+}
+
+impl Default for PlayerPhysics {
+    fn default() -> PlayerPhysics {
+        PlayerPhysics {
+            gravity: 1200.0,
+            run_acceleration: 600.0,
+            friction: 2.0,
+            jump_speed: 600.0,
+            jump_cut: 0.5,
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+        }
+    }
+}
+
+impl PlayerPhysics {
+    pub fn load(path: &Path) -> Result<PlayerPhysics, SettingsError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| SettingsError::Io(path.to_owned(), err))?;
+        toml::from_str(&content).map_err(|err| SettingsError::Parse(path.to_owned(), err))
+    }
+}
+
+/// Hits the player can take before dying.
+pub const MAX_HEALTH: u32 = 3;
+/// Seconds the player can't be hurt again after being hurt or respawning.
+const INVULNERABLE_TIME: f64 = 1.5;
+/// Seconds the player is shown, then hidden, while it can't be hurt.
+const BLINK_TIME: f64 = 0.1;
+/// Speed the player is knocked away and up at when hurt.
+const KNOCKBACK_SPEED: f64 = 250.0;
+const KNOCKBACK_LIFT: f64 = 300.0;
 
 pub struct Player {
-    sprite: Sprite,
-    controller: Controller,
+    animator: Animator,
+    landing: bool,
+    /// What the player is asked to do, set from input before each update.
+    pub controller: Controller,
     ground: bool,
     pub rect: Rect,
+    /// Where the player was before the last update, to draw in between.
+    previous: Vec2d,
     acc: Vec2d,
     pub vel: Vec2d,
-    friction: f64,
+    pub physics: PlayerPhysics,
     collider: Collider,
     flip: bool,
+    /// Whether jump was held in the last update, to tell presses apart.
+    was_up: bool,
+    /// Whether the player is rising from a jump that can still be cut.
+    jumping: bool,
+    /// Seconds since jump was last pressed, and since the player last stood
+    /// on the ground, for jump buffering and coyote time.
+    since_press: f64,
+    since_ground: f64,
+    pub health: u32,
+    /// Seconds left before the player can be hurt again.
+    invulnerable: f64,
 }
 
 impl Player {
-    pub fn new(sprite: Sprite, rect: Rect) -> Player {
+    pub fn new(rect: Rect, animator: Animator, physics: PlayerPhysics) -> Player {
         Player {
-            sprite: sprite,
-            controller: Controller {
-                up: false,
-                left: false,
-                right: false,
-            },
+            animator,
+            landing: false,
+            controller: Controller::default(),
             ground: false,
-            rect: rect,
+            previous: Vec2d {
+                x: rect.x,
+                y: rect.y,
+            },
+            rect,
             acc: Vec2d::new(),
             vel: Vec2d::new(),
-            friction: 2.0,
-            collider: Collider { interact: None },
+            physics,
+            collider: Collider::default(),
             flip: false,
+            was_up: false,
+            jumping: false,
+            since_press: f64::INFINITY,
+            since_ground: f64::INFINITY,
+            health: MAX_HEALTH,
+            invulnerable: 0.0,
         }
     }
 
-    pub fn update(&mut self, dt: f64, object: &mut Vec<Object>) {
-        self.acc = Vec2d { x: 0.0, y: 20.0 };
+    /// Moves the player by one simulation step of `dt` seconds.
+    pub fn update(&mut self, dt: f64, object: &[Object], grid: &Grid) {
+        let was_ground = self.ground;
+        self.previous = Vec2d {
+            x: self.rect.x,
+            y: self.rect.y,
+        };
+        let physics = &self.physics;
+        self.acc = Vec2d {
+            x: 0.0,
+            y: physics.gravity,
+        };
         self.ground = false;
 
         if self.controller.left {
-            self.acc.x = -10.0;
+            self.acc.x = -physics.run_acceleration;
             self.flip = true;
         };
         if self.controller.right {
-            self.acc.x = 10.0;
+            self.acc.x = physics.run_acceleration;
             self.flip = false;
         };
 
-        let collision = self.collider.collision(&self.rect, &object.to_vec());
-
-        if collision {
-            if let Some((interact, pos)) = &self.collider.interact {
-                match interact {
-                    Interact::BOTTOM => {
-                        self.ground = true;
-                        self.vel.y = 0.0;
-                        self.rect.y = *pos;
-                    }
-                    Interact::TOP => {
-                        self.vel.y = 0.0;
-                        self.rect.y = *pos;
-                    }
-                    Interact::RIGHT => {
-                        self.vel.x = 0.0;
-                        self.rect.x = *pos;
-                    }
-                    Interact::LEFT => {
-                        self.vel.x = 0.0;
-                        self.rect.x = *pos;
-                    }
-                    _ => {}
-                }
+        self.acc.x += self.vel.x * -physics.friction;
+        self.vel.add(self.acc.x * dt, self.acc.y * dt);
+        let (dx, dy) = (self.vel.x * dt, self.vel.y * dt);
+
+        let solids = grid.solids(object, &self.rect, dx, dy);
+        self.collider.sweep(&mut self.rect, dx, dy, &solids);
+
+        for contact in self.collider.contacts.iter() {
+            let (nx, ny) = contact.interact.normal();
+            if nx != 0.0 {
+                self.vel.x = 0.0;
+            }
+            if ny != 0.0 {
+                self.vel.y = 0.0;
+            }
+            if ny < 0.0 {
+                self.ground = true;
             }
         }
 
-        if self.controller.up {
-            if self.ground {
-                self.vel.y = -10.0;
-                self.ground = false;
-            };
-        };
+        self.jump(dt);
+        self.animate(dt, was_ground);
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+    }
 
-        self.acc.x += self.vel.x * -self.friction;
-        self.vel.add(self.acc.x * dt, self.acc.y * dt);
+    /// Jumps when jump was pressed recently and the player stands, or just
+    /// stood, on the ground, and cuts the jump short when it is released.
+    fn jump(&mut self, dt: f64) {
+        let up = self.controller.up;
+        if up && !self.was_up {
+            self.since_press = 0.0;
+        }
+        self.was_up = up;
+        if self.ground {
+            self.since_ground = 0.0;
+        }
 
-        self.rect.x += self.vel.x;
-        self.rect.y += self.vel.y;
+        let physics = &self.physics;
+        if self.since_press <= physics.jump_buffer && self.since_ground <= physics.coyote_time {
+            self.vel.y = -physics.jump_speed;
+            self.ground = false;
+            self.jumping = true;
+            self.since_press = f64::INFINITY;
+            self.since_ground = f64::INFINITY;
+        } else if self.jumping && (!up || self.vel.y >= 0.0) {
+            if self.vel.y < 0.0 {
+                self.vel.y *= self.physics.jump_cut;
+            }
+            self.jumping = false;
+        }
+
+        self.since_press += dt;
+        self.since_ground += dt;
     }
 
-    pub fn key_event(&mut self, e: &Event) {
-        if let Some(b) = e.press_args() {
-            if let Button::Keyboard(key) = b {
-                match key {
-                    Key::Left => self.controller.left = true,
-                    Key::Right => self.controller.right = true,
-                    Key::Space => self.controller.up = true,
-                    _ => {}
-                }
-            }
+    /// Picks the clip matching how the player moves and advances it.
+    fn animate(&mut self, dt: f64, was_ground: bool) {
+        if self.ground && !was_ground {
+            self.landing = true;
         }
+        if !self.ground {
+            self.landing = false;
+        }
+
+        let clip = if !self.ground {
+            if self.vel.y < 0.0 {
+                "jump"
+            } else {
+                "fall"
+            }
+        } else if self.landing {
+            "land"
+        } else if self.vel.x.abs() > 30.0 {
+            "run"
+        } else {
+            "idle"
+        };
+        self.animator.play(clip);
 
-        if let Some(b) = e.release_args() {
-            if let Button::Keyboard(key) = b {
-                match key {
-                    Key::Left => self.controller.left = false,
-                    Key::Right => self.controller.right = false,
-                    Key::Space => self.controller.up = false,
-                    _ => {}
-                }
+        if let Some(event) = self.animator.update(dt) {
+            if event == "landed" {
+                self.landing = false;
             }
         }
     }
-}
 
-impl SpriteEvent for Player {
-    fn render(&mut self, e: &Event, w: &mut PistonWindow) {
-        let texture = self.sprite.get_texture(if self.flip { 1 } else { 0 });
-        let rect = &self.rect;
-        w.draw_2d(e, |c, g, _d| {
-            image(
-                texture,
-                c.trans(rect.x, rect.y)
-                    .scale(
-                        rect.scale / texture.get_width() as f64,
-                        rect.scale / texture.get_height() as f64,
-                    )
-                    .transform,
-                g,
-            );
-        });
+    /// 1 when facing right, -1 when facing left.
+    pub fn facing(&self) -> f64 {
+        if self.flip {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.ground
+    }
+
+    /// Objects the player hit its head on in the last update.
+    pub fn bumped(&self) -> Vec<usize> {
+        self.collider
+            .contacts
+            .iter()
+            .filter(|contact| contact.interact == Interact::Top)
+            .map(|contact| contact.target)
+            .collect()
+    }
+
+    /// Where to draw the player, `alpha` of the way from where it was before
+    /// the last update to where it is now.
+    pub fn interpolated(&self, alpha: f64) -> Rect {
+        Rect {
+            x: self.previous.x + (self.rect.x - self.previous.x) * alpha,
+            y: self.previous.y + (self.rect.y - self.previous.y) * alpha,
+            ..self.rect.clone()
+        }
+    }
+
+    /// Index in the sprite sheet of the frame to draw.
+    pub fn frame(&self) -> usize {
+        self.animator.frame()
+    }
+
+    /// Takes a hit from something at `from_x`, knocking the player away from
+    /// it, unless the player was hurt too recently. Returns whether it was
+    /// hurt.
+    pub fn hurt(&mut self, from_x: f64) -> bool {
+        if self.invulnerable > 0.0 {
+            return false;
+        }
+        self.health = self.health.saturating_sub(1);
+        self.invulnerable = INVULNERABLE_TIME;
+        let away = if self.rect.center().x < from_x {
+            -1.0
+        } else {
+            1.0
+        };
+        self.vel = Vec2d {
+            x: away * KNOCKBACK_SPEED,
+            y: -KNOCKBACK_LIFT,
+        };
+        self.ground = false;
+        self.jumping = false;
+        true
+    }
+
+    /// Whether to draw the player, which blinks while it can't be hurt.
+    pub fn visible(&self) -> bool {
+        ((self.invulnerable / BLINK_TIME) as u32).is_multiple_of(2)
+    }
+
+    /// Bounces the player up off something it landed on, as high as a
+    /// jump held for as long as jump is.
+    pub fn bounce(&mut self, speed: f64) {
+        self.vel.y = -speed;
+        self.ground = false;
+        self.jumping = true;
+    }
+
+    /// Puts the player back at `x`, `y` at rest, briefly safe from harm.
+    pub fn respawn(&mut self, x: f64, y: f64) {
+        self.rect.x = x;
+        self.rect.y = y;
+        self.previous = Vec2d { x, y };
+        self.acc = Vec2d::new();
+        self.vel = Vec2d::new();
+        self.ground = false;
+        self.jumping = false;
+        self.since_press = f64::INFINITY;
+        self.since_ground = f64::INFINITY;
+        self.invulnerable = INVULNERABLE_TIME;
     }
 }
@@ -0,0 +1,243 @@
+use crate::libs::Controller;
+use crate::player::PlayerPhysics;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the input of a session comes from, and whether it is kept.
+pub enum Session {
+    Live,
+    Record(PathBuf),
+    Replay(Replay),
+}
+
+/// Input of one simulation step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tick {
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    /// Whether the player goes back to its checkpoint before the step.
+    pub respawn: bool,
+}
+
+impl Tick {
+    pub fn new(controller: &Controller, respawn: bool) -> Tick {
+        Tick {
+            left: controller.left,
+            right: controller.right,
+            jump: controller.up,
+            respawn,
+        }
+    }
+
+    pub fn controller(&self) -> Controller {
+        Controller {
+            up: self.jump,
+            left: self.left,
+            right: self.right,
+        }
+    }
+}
+
+/// `ticks` steps in a row with the same input.
+#[derive(Serialize, Deserialize)]
+struct Run {
+    ticks: u32,
+    input: Tick,
+}
+
+/// A fingerprint of where the player is, to tell a replay drifted without
+/// keeping every position: FNV-1a over the exact bits, so any difference
+/// shows.
+fn fingerprint(position: [f64; 2]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in position.iter().flat_map(|v| v.to_bits().to_le_bytes()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Everything needed to play a session again exactly: the map, the physics
+/// it was played with and the input of every simulation step.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replay {
+    pub map: PathBuf,
+    pub physics: PlayerPhysics,
+    runs: Vec<Run>,
+    /// Fingerprint of where the player was after each step.
+    #[serde(default)]
+    positions: Vec<u32>,
+}
+
+impl Replay {
+    pub fn new(map: PathBuf, physics: PlayerPhysics) -> Replay {
+        Replay {
+            map,
+            physics,
+            runs: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| ReplayError::Io(path.to_owned(), err))?;
+        serde_json::from_str(&content).map_err(|err| ReplayError::Json(path.to_owned(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| ReplayError::Json(path.to_owned(), err))?;
+        fs::write(path, content).map_err(|err| ReplayError::Io(path.to_owned(), err))
+    }
+
+    /// Adds a step played with `input`, after which the player was at
+    /// `position`.
+    pub fn record(&mut self, input: Tick, position: [f64; 2]) {
+        match self.runs.last_mut() {
+            Some(run) if run.input == input => run.ticks += 1,
+            _ => self.runs.push(Run { ticks: 1, input }),
+        }
+        self.positions.push(fingerprint(position));
+    }
+
+    /// Input of step `tick`, counted from 0.
+    pub fn tick(&self, tick: usize) -> Option<Tick> {
+        let mut start = 0;
+        for run in self.runs.iter() {
+            let end = start + run.ticks as usize;
+            if tick < end {
+                return Some(run.input);
+            }
+            start = end;
+        }
+        None
+    }
+}
+
+/// Plays a replay back one step at a time.
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback { replay, tick: 0 }
+    }
+
+    /// Input of the next step, or `None` once the replay is over.
+    pub fn next(&mut self) -> Option<Tick> {
+        let input = self.replay.tick(self.tick)?;
+        self.tick += 1;
+        Some(input)
+    }
+
+    /// Checks the player ended the last step where it did when recording,
+    /// returning the step it drifted on otherwise. Nothing is checked
+    /// before the first step, or in replays recorded without positions.
+    pub fn check(&self, position: [f64; 2]) -> Result<(), usize> {
+        let step = match self.tick.checked_sub(1) {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+        match self.replay.positions.get(step) {
+            Some(expected) if *expected != fingerprint(position) => Err(step),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Records every step, and saves it to `path` when dropped, so a session is
+/// kept even if the game panics.
+pub struct Recorder {
+    pub replay: Replay,
+    path: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(replay: Replay, path: PathBuf) -> Recorder {
+        Recorder { replay, path }
+    }
+
+    /// Records from the start of a fresh world, as what was recorded so
+    /// far doesn't lead to it.
+    pub fn start_over(&mut self) {
+        let map = self.replay.map.clone();
+        self.replay = Replay::new(map, self.replay.physics.clone());
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.replay.save(&self.path) {
+            eprintln!("warning: {}", err);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ReplayError::Json(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_back_recorded_ticks_and_positions() {
+        let mut replay = Replay::new(PathBuf::from("map.txt"), PlayerPhysics::default());
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        replay.record(Tick::default(), [0.0, 0.1]);
+        replay.record(right, [0.1, 0.2]);
+        replay.record(right, [1.0 / 3.0, 0.2]);
+
+        let json = serde_json::to_string(&replay).unwrap();
+        let replay: Replay = serde_json::from_str(&json).unwrap();
+        assert_eq!(replay.runs.len(), 2);
+
+        let mut playback = Playback::new(replay);
+        assert_eq!(playback.check([9.0, 9.0]), Ok(()));
+        assert_eq!(playback.next(), Some(Tick::default()));
+        assert_eq!(playback.check([0.0, 0.1]), Ok(()));
+        assert_eq!(playback.next(), Some(right));
+        // every step is checked, not only the last of a run of input
+        assert_eq!(playback.check([0.1, 0.2]), Ok(()));
+        assert_eq!(playback.check([5.0, 5.0]), Err(1));
+        assert_eq!(playback.next(), Some(right));
+        assert_eq!(playback.check([1.0 / 3.0, 0.2]), Ok(()));
+        assert_eq!(playback.check([0.3333333333333333, 0.2]), Ok(()));
+        assert_eq!(playback.check([1.0 / 3.0 + 1e-15, 0.2]), Err(2));
+        assert_eq!(playback.next(), None);
+    }
+
+    #[test]
+    fn replays_without_positions_still_play() {
+        let json = r#"{"map": "map.txt", "physics": {}, "runs": [{"ticks": 2, "input": {}}]}"#;
+        let mut playback = Playback::new(serde_json::from_str(json).unwrap());
+        assert_eq!(playback.next(), Some(Tick::default()));
+        assert_eq!(playback.check([5.0, 5.0]), Ok(()));
+        assert_eq!(playback.next(), Some(Tick::default()));
+        assert_eq!(playback.next(), None);
+    }
+}
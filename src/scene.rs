@@ -1,202 +1,262 @@
 use piston_window::*;
 
+use crate::animation::Animator;
+use crate::assets::{Handle, Manifest, Registry};
 use crate::camera::Camera;
-use crate::libs::{Rect, Tilemap};
-use crate::object::Object;
-use crate::player::Player;
-use crate::sprite::{Sprite, SpriteEvent};
-use core::time;
-use std::collections::{BTreeMap, VecDeque};
+use crate::campaign::Campaign;
+use crate::game::Game;
+use crate::hud::Hud;
+use crate::input::Input;
+use crate::libs::FileWatch;
+use crate::loader::{LevelData, Loaded, Loader, Names};
+use crate::player::PlayerPhysics;
+use crate::replay::{Playback, Recorder, Replay, Session};
+use crate::sprite::{Sprite, Tileset};
+use crate::state::{self, Playing, State, StateStack, Title};
+use crate::world::World;
+use std::mem;
 use std::path::PathBuf;
-use std::thread;
-
-#[derive(Debug)]
-pub enum LoadProgress {
-    Sprites,
-    Objects,
-    Player,
-}
+use std::time::Instant;
 
 pub struct Scene {
-    player: Option<Player>,
-    objects: Vec<Object>,
-    camera: Option<Camera>,
-    is_loaded: bool,
-    sprites: BTreeMap<String, Sprite>,
-    load_progress: VecDeque<LoadProgress>,
-    progress_value: f64,
-    max_progress_value: f64,
+    /// Everything loaded, once it is.
+    game: Option<Game>,
+    states: StateStack,
+    /// Loads the game, then any other level played after the first.
+    loader: Option<Loader>,
+    /// What the game is made of while it loads.
+    level: Option<LevelData>,
+    sprites: Registry<Sprite>,
+    /// Sprites of the manifest and the player, which any level may use.
+    kept: Vec<Handle<Sprite>>,
+    /// Sprites of the level's own tilesets, while it is loaded or played.
+    level_sprites: Vec<Handle<Sprite>>,
+    /// Why loading failed, which is shown instead of the game.
+    error: Option<String>,
+    campaign: Option<Campaign>,
+    session: Option<Session>,
     assets: PathBuf,
+    /// When the last frame was drawn, to play the time since in the next.
+    last_frame: Option<Instant>,
 }
 
 impl Scene {
-    pub fn new(assets: PathBuf) -> Scene {
+    pub fn new(assets: PathBuf, manifest: Manifest, campaign: Campaign, session: Session) -> Scene {
+        let map = campaign.map().to_path_buf();
         Scene {
-            player: None,
-            objects: vec![],
-            camera: None,
-            is_loaded: false,
-            sprites: BTreeMap::new(),
-            load_progress: VecDeque::from([
-                LoadProgress::Sprites,
-                LoadProgress::Objects,
-                LoadProgress::Player,
-            ]),
-            progress_value: 0.0,
-            max_progress_value: 3.0,
+            game: None,
+            states: StateStack::new(Vec::new()),
+            loader: Some(Loader::new(assets.clone(), manifest, map)),
+            level: None,
+            sprites: Registry::default(),
+            kept: Vec::new(),
+            level_sprites: Vec::new(),
+            error: None,
+            campaign: Some(campaign),
+            session: Some(session),
             assets,
+            last_frame: None,
         }
     }
 
+    /// Uploads whatever the loader has decoded since the last call, and
+    /// starts the level once it is all there.
     fn load(&mut self, w: &mut PistonWindow) {
-        if let Some(state) = self.load_progress.pop_front() {
-            match state {
-                LoadProgress::Sprites => {
-                    let assets = &self.assets;
-                    let ground_sprite =
-                        Sprite::load_texture_as_sprite(assets.join("ground.png"), w, Flip::None);
-                    let brick_sprite =
-                        Sprite::load_texture_as_sprite(assets.join("brick.png"), w, Flip::None);
-                    let brick2_sprite =
-                        Sprite::load_texture_as_sprite(assets.join("brick2.png"), w, Flip::None);
-                    let cloud_sprite =
-                        Sprite::load_texture_as_sprite(assets.join("cloud.png"), w, Flip::None);
-                    let mut player_sprite =
-                        Sprite::load_texture_as_sprite(assets.join("player.png"), w, Flip::None);
-                    let player_texture_flip =
-                        Sprite::load_texture(assets.join("player.png"), w, Flip::Horizontal);
-                    player_sprite.add_texture(player_texture_flip);
-
-                    self.sprites.insert("ground".to_owned(), ground_sprite);
-                    self.sprites.insert("brick".to_owned(), brick_sprite);
-                    self.sprites.insert("brick2".to_owned(), brick2_sprite);
-                    self.sprites.insert("cloud".to_owned(), cloud_sprite);
-                    self.sprites.insert("player".to_owned(), player_sprite);
-                    self.progress_value += 1.0;
+        let loaded = match self.loader.as_mut().map(Loader::poll) {
+            Some(Some(loaded)) => loaded,
+            Some(None) => {
+                self.loader = None;
+                return self.start(w);
+            }
+            None => return,
+        };
+        // levels after the first add their images to the game's
+        let sprites = match self.game.as_mut() {
+            Some(game) => &mut game.sprites,
+            None => &mut self.sprites,
+        };
+        for item in loaded {
+            match item {
+                Loaded::Level { data, .. } => {
+                    for warning in data.level.warnings.iter() {
+                        eprintln!("warning: {}: {}", data.map.display(), warning);
+                    }
+                    self.level = Some(*data);
                 }
-                LoadProgress::Objects => {
-                    let tilemap = Tilemap::new(self.assets.join("map.txt").to_str().unwrap());
-                    let objects = &mut self.objects;
-
-                    for (row, tiles) in tilemap.iter().enumerate() {
-                        for (col, tile) in tiles.iter().enumerate() {
-                            if let Some(ground_sprite) = self.sprites.get("ground") {
-                                if *tile == '1' {
-                                    let rect = Rect::new(
-                                        col as f64 * 40.0,
-                                        row as f64 * 40.0,
-                                        0.0,
-                                        0.0,
-                                        40.0,
-                                    );
-                                    let object = Object::new(ground_sprite.clone(), rect, true);
-                                    objects.push(object);
-                                }
-                            }
-
-                            if let Some(brick_sprite) = self.sprites.get("brick") {
-                                if *tile == '2' {
-                                    let rect = Rect::new(
-                                        col as f64 * 40.0,
-                                        row as f64 * 40.0,
-                                        0.0,
-                                        0.0,
-                                        40.0,
-                                    );
-                                    let object = Object::new(brick_sprite.clone(), rect, true);
-                                    objects.push(object);
-                                }
-                            }
-
-                            if let Some(brick2_sprite) = self.sprites.get("brick2") {
-                                if *tile == '?' {
-                                    let rect = Rect::new(
-                                        col as f64 * 40.0,
-                                        row as f64 * 40.0,
-                                        0.0,
-                                        0.0,
-                                        40.0,
-                                    );
-                                    let object = Object::new(brick2_sprite.clone(), rect, true);
-                                    objects.push(object);
+                Loaded::Image { asset, image, .. } => {
+                    let tileset = Tileset::slice(
+                        Sprite::upload(&image, w),
+                        asset.tile_width,
+                        asset.tile_height,
+                        asset.margin,
+                        asset.spacing,
+                    );
+                    match asset.names {
+                        Names::Each(names) => {
+                            for (idx, name) in names.into_iter().enumerate() {
+                                if let Some(sprite) = tileset.sprite(idx) {
+                                    self.kept.push(sprites.insert(name, sprite));
                                 }
                             }
-
-                            if let Some(cloud_sprite) = self.sprites.get("cloud") {
-                                if *tile == '@' {
-                                    let rect = Rect::new(
-                                        col as f64 * 40.0,
-                                        row as f64 * 40.0,
-                                        0.0,
-                                        0.0,
-                                        40.0,
-                                    );
-                                    let object = Object::new(cloud_sprite.clone(), rect, false);
-                                    objects.push(object);
+                        }
+                        Names::Frames(name) => {
+                            self.kept.push(sprites.insert(name, tileset.sprites()));
+                        }
+                        Names::Numbered(name) => {
+                            for idx in 0..tileset.len() {
+                                if let Some(sprite) = tileset.sprite(idx) {
+                                    let name = format!("{}:{}", name, idx);
+                                    self.level_sprites.push(sprites.insert(name, sprite));
                                 }
                             }
                         }
                     }
+                }
+                Loaded::Failed(err) => return self.fail(err),
+            }
+        }
+    }
 
-                    let size = w.size();
-
-                    let [width, height] = [size.width, size.height];
-
-                    let max_w = tilemap.first().unwrap().len() as f64 * 40.0;
-                    let max_h = tilemap.len() as f64 * 40.0;
-
-                    let cam_x = width / 2.0 - 50.0;
-                    let cam_y = height / 2.0 - 50.0;
+    /// Plays the level loaded, making the game out of everything else
+    /// loaded along with the first.
+    fn start(&mut self, w: &mut PistonWindow) {
+        let LevelData {
+            map,
+            mut level,
+            name,
+            camera,
+            animations,
+        } = self.level.take().expect("asset loader stopped early");
+        let sprites = match self.game.as_ref() {
+            Some(game) => &game.sprites,
+            None => &self.sprites,
+        };
+        level.tiles.retain(|tile| match &tile.sprite {
+            Some(sprite) if !sprites.contains(sprite) => {
+                eprintln!(
+                    "warning: {}: row {}, col {}: unknown sprite '{}'",
+                    map.display(),
+                    tile.row + 1,
+                    tile.col + 1,
+                    sprite
+                );
+                false
+            }
+            _ => true,
+        });
+        let max_w = level.width as f64 * 40.0;
+        let max_h = level.height as f64 * 40.0;
+        let mut camera = Camera::new(camera, max_w, max_h);
+        let size = w.size();
+        camera.resize(size.width, size.height);
+        let hud = Hud::new(name);
+        if let Some(game) = self.game.as_mut() {
+            game.next_level(level, camera, hud);
+            // the last level's own images go along with its world
+            game.sprites.release();
+            return;
+        }
 
-                    self.camera = Some(Camera::new(cam_x, cam_y, 100.0, 100.0, max_w, max_h));
-                    self.progress_value += 1.0;
-                }
-                LoadProgress::Player => {
-                    if let Some(player_sprite) = self.sprites.get("player") {
-                        let player_rect = Rect::new(0.0, 0.0, 5.0, 0.0, 40.0);
-                        self.player = Some(Player::new(player_sprite.clone(), player_rect));
-                        self.progress_value += 1.0;
-                    }
-                }
+        let clips = animations.expect("player animations weren't loaded").clips;
+        let animator = Animator::new(clips.clone(), "idle");
+        // physics stay as recorded, or the replay would drift
+        let physics_path = self.assets.join("player_physics.toml");
+        let session = self.session.take();
+        let physics = match &session {
+            Some(Session::Replay(replay)) => Ok(replay.physics.clone()),
+            _ => PlayerPhysics::load(&physics_path),
+        };
+        let input = Input::load(&self.assets.join("input.toml"));
+        let (physics, input) = match physics.and_then(|physics| Ok((physics, input?))) {
+            Ok(loaded) => loaded,
+            Err(err) => return self.fail(err.to_string()),
+        };
+        let (mut watch, mut recorder, mut playback) = (None, None, None);
+        match session {
+            Some(Session::Replay(replay)) => playback = Some(Playback::new(replay)),
+            Some(Session::Record(path)) => {
+                let replay = Replay::new(map, physics.clone());
+                recorder = Some(Recorder::new(replay, path));
             }
-        } else {
-            self.is_loaded = true;
+            _ => watch = Some(FileWatch::new(physics_path, 0.5)),
         }
+        let world = World::new(level, physics, animator, &self.sprites);
+        camera.focus(&world.player);
 
-        if self.progress_value < self.max_progress_value {
-            thread::sleep(time::Duration::from_millis(500));
+        let sprites = mem::take(&mut self.sprites);
+        let mut game = Game::new(world, camera, input, hud, sprites, clips);
+        // a replay plays straight away, there's no one to press start
+        let mut states: Vec<Box<dyn State>> = vec![Box::new(Playing)];
+        if playback.is_none() {
+            states.push(Box::new(Title));
         }
+        game.physics = watch;
+        game.recorder = recorder;
+        game.playback = playback;
+        game.campaign = self.campaign.take().unwrap();
+        self.states = StateStack::new(states);
+        self.game = Some(game);
+    }
+
+    /// Stops loading, showing `err` instead of the game.
+    fn fail(&mut self, err: String) {
+        eprintln!("error: {}", err);
+        self.error = Some(err);
+        self.loader = None;
     }
 
     pub fn update(&mut self, e: &Event, w: &mut PistonWindow, glyphs: &mut Glyphs) {
-        w.draw_2d(e, |_, g, _d| {
-            clear(color::hex("aaeeffff"), g);
-        });
         let width = w.size().width;
         let height = w.size().height;
-        if self.is_loaded {
-            let player = self.player.as_mut().unwrap();
-            let camera = self.camera.as_mut().unwrap();
-
-            for object in self.objects.iter_mut().filter(|o| {
-                o.rect.x.round() >= -40.0
-                    && o.rect.x.round() <= width
-                    && o.rect.y.round() >= -40.0
-                    && o.rect.x.round() <= height
-            }) {
-                object.render(e, w);
+        let loading = self.loader.is_some() || self.error.is_some();
+        if let Some(game) = self.game.as_mut().filter(|_| !loading) {
+            game.camera.resize(width, height);
+            for action in game.input.event(e) {
+                self.states.action(action, game);
             }
-
-            player.render(e, w);
-            player.key_event(e);
-
-            if let Some(u) = e.update_args() {
-                player.update(u.dt, &mut self.objects);
-                camera.update(player, &mut self.objects);
+            // the game plays as much time as really passed since the last
+            // frame, however long updates take to come
+            if e.render_args().is_some() {
+                let now = Instant::now();
+                let dt = self
+                    .last_frame
+                    .map_or(0.0, |last| (now - last).as_secs_f64());
+                self.last_frame = Some(now);
+                self.states.update(dt, game);
             }
+            if self.states.take_load() {
+                let map = game.campaign.map().to_path_buf();
+                // the level played is done with, save what its world holds
+                self.level_sprites.clear();
+                self.loader = Some(Loader::level(self.assets.clone(), map));
+            }
+
+            let states = &self.states;
+            w.draw_2d(e, |c, g, d| {
+                clear(color::hex("aaeeffff"), g);
+                states.render(game, &c, g, glyphs);
+                glyphs.factory.encoder.flush(d);
+            });
         } else {
+            // keep track of what is held, so nothing is stuck once the
+            // level is loaded
+            if let Some(game) = self.game.as_mut() {
+                game.input.event(e);
+            }
+            // the time spent loading isn't played
+            self.last_frame = None;
+            if let Some(err) = self.error.as_ref() {
+                w.draw_2d(e, |c, g, d| {
+                    clear(color::hex("aaeeffff"), g);
+                    failure(err, &c, g, glyphs);
+                    glyphs.factory.encoder.flush(d);
+                });
+                return;
+            }
             w.draw_2d(e, |c, g, d| {
-                let load_percentage = self.progress_value / self.max_progress_value * 100.0;
+                clear(color::hex("aaeeffff"), g);
+                let progress = self.loader.as_ref().map_or(0.0, Loader::progress);
+                let load_percentage = progress * 100.0;
                 let font_size = 48;
                 let loading_str = format!("Loading {}%", load_percentage as i8);
                 let loading_text = text::Text::new_color([1.0, 1.0, 1.0, 1.0], font_size).round();
@@ -233,7 +293,7 @@ impl Scene {
                     [
                         width / 4.0,
                         (height - loading_progress_height) / 2.0,
-                        width / 2.0 * self.progress_value / self.max_progress_value,
+                        width / 2.0 * progress,
                         loading_progress_height,
                     ],
                     &c.draw_state,
@@ -242,7 +302,7 @@ impl Scene {
                 );
                 glyphs.factory.encoder.flush(d);
             });
-            if let Some(_) = e.idle_args() {
+            if e.update_args().is_some() {
                 self.load(w);
             }
         }
@@ -255,3 +315,24 @@ impl Scene {
         // });
     }
 }
+
+/// Shows why loading failed, wrapped to fit the window, since the game
+/// can't go on without what it was loading.
+fn failure(err: &str, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+    let size = 16;
+    let width = c.get_view_size()[0] - 40.0;
+    let mut lines: Vec<String> = Vec::new();
+    for word in err.split_whitespace() {
+        let longer = lines.last().map(|line| format!("{} {}", line, word));
+        match longer {
+            Some(longer) if glyphs.width(size, &longer).unwrap_or(0.0) <= width => {
+                *lines.last_mut().unwrap() = longer;
+            }
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    let mut caption = vec![("Can't load the game", 32)];
+    caption.extend(lines.iter().map(|line| (line.as_str(), size)));
+    caption.push(("Press Esc to quit", size));
+    state::caption(&caption, c, g, glyphs);
+}
@@ -0,0 +1,85 @@
+/// Points for picking up a coin.
+const COIN_POINTS: u32 = 200;
+/// Points for breaking a brick.
+const BRICK_POINTS: u32 = 50;
+/// Points for stomping an enemy.
+const STOMP_POINTS: u32 = 100;
+/// Coins that make up an extra life.
+const COINS_PER_LIFE: u32 = 100;
+/// Lives the player starts with.
+const START_LIVES: u32 = 3;
+
+/// How well the player is doing, shown on the HUD.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    pub points: u32,
+    /// Coins towards the next extra life.
+    pub coins: u32,
+    pub lives: u32,
+    /// Times the player died, which losing lives doesn't show once extra
+    /// lives are won back.
+    pub deaths: u32,
+    /// Seconds spent in the level, not counting pauses.
+    pub time: f64,
+}
+
+impl Default for Score {
+    fn default() -> Score {
+        Score {
+            points: 0,
+            coins: 0,
+            lives: START_LIVES,
+            deaths: 0,
+            time: 0.0,
+        }
+    }
+}
+
+impl Score {
+    /// Counts picking up an item of `kind`.
+    pub fn collect(&mut self, kind: &str) {
+        if kind == "coin" {
+            self.points += COIN_POINTS;
+            self.coins += 1;
+            if self.coins == COINS_PER_LIFE {
+                self.coins = 0;
+                self.lives += 1;
+            }
+        }
+    }
+
+    pub fn broke_brick(&mut self) {
+        self.points += BRICK_POINTS;
+    }
+
+    pub fn stomped(&mut self) {
+        self.points += STOMP_POINTS;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_hundred_coins_give_a_life() {
+        let mut score = Score::default();
+        for _ in 0..COINS_PER_LIFE - 1 {
+            score.collect("coin");
+        }
+        assert_eq!(
+            (score.coins, score.lives),
+            (COINS_PER_LIFE - 1, START_LIVES)
+        );
+        score.collect("coin");
+        assert_eq!((score.coins, score.lives), (0, START_LIVES + 1));
+        assert_eq!(score.points, COINS_PER_LIFE * COIN_POINTS);
+    }
+
+    #[test]
+    fn unknown_items_are_worth_nothing() {
+        let mut score = Score::default();
+        score.collect("mushroom");
+        assert_eq!(score, Score::default());
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A tile position marked in the map as a place to (re)spawn the player,
+/// counted from 0 like the rows and columns of a level.
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnPoint {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl SpawnPoint {
+    pub fn position(&self, tile_size: f64) -> (f64, f64) {
+        (self.col as f64 * tile_size, self.row as f64 * tile_size)
+    }
+}
+
+#[derive(Clone)]
+pub struct Spawns {
+    pub player: SpawnPoint,
+    pub checkpoints: BTreeMap<String, SpawnPoint>,
+}
+
+/// Gathers spawn markers while a map is read, rejecting duplicates.
+#[derive(Default)]
+pub struct SpawnCollector {
+    player: Option<SpawnPoint>,
+    checkpoints: BTreeMap<String, SpawnPoint>,
+}
+
+impl SpawnCollector {
+    pub fn player(&mut self, point: SpawnPoint) -> Result<(), SpawnError> {
+        if let Some(first) = self.player {
+            return Err(SpawnError::DuplicatePlayer {
+                first,
+                second: point,
+            });
+        }
+        self.player = Some(point);
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self, name: &str, point: SpawnPoint) -> Result<(), SpawnError> {
+        if let Some(first) = self.checkpoints.get(name) {
+            return Err(SpawnError::DuplicateCheckpoint {
+                name: name.to_owned(),
+                first: *first,
+                second: point,
+            });
+        }
+        self.checkpoints.insert(name.to_owned(), point);
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<Spawns, SpawnError> {
+        match self.player {
+            Some(player) => Ok(Spawns {
+                player,
+                checkpoints: self.checkpoints,
+            }),
+            None => Err(SpawnError::MissingPlayer),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    MissingPlayer,
+    DuplicatePlayer {
+        first: SpawnPoint,
+        second: SpawnPoint,
+    },
+    DuplicateCheckpoint {
+        name: String,
+        first: SpawnPoint,
+        second: SpawnPoint,
+    },
+}
+
+impl fmt::Display for SpawnPoint {
+    /// Rows and columns count from 1 in messages, like those about the
+    /// legend.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "row {}, col {}", self.row + 1, self.col + 1)
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpawnError::MissingPlayer => write!(f, "map has no player spawn marker"),
+            SpawnError::DuplicatePlayer { first, second } => write!(
+                f,
+                "map has more than one player spawn marker ({} and {})",
+                first, second
+            ),
+            SpawnError::DuplicateCheckpoint {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "checkpoint '{}' is defined more than once ({} and {})",
+                name, first, second
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::legend::Legend;
+    use crate::level::Level;
+    use crate::libs::Tilemap;
+
+    fn parse(map: &str) -> Result<Level, String> {
+        let legend: Legend = toml::from_str(
+            r#"
+            [tiles."="]
+            [tiles.P]
+            behavior = "spawn"
+            [tiles.a]
+            behavior = { checkpoint = "a" }
+            "#,
+        )
+        .unwrap();
+        Level::from_tilemap(&Tilemap::parse(map), &legend).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn markers_place_the_player_and_checkpoints() {
+        let level = parse("====\n=P=a\n").unwrap();
+        assert_eq!(level.spawns.player.row, 1);
+        assert_eq!(level.spawns.player.col, 1);
+        let checkpoint = level.spawns.checkpoints["a"];
+        assert_eq!((checkpoint.row, checkpoint.col), (1, 3));
+        assert_eq!(checkpoint.position(40.0), (120.0, 40.0));
+    }
+
+    #[test]
+    fn a_map_needs_exactly_one_player_marker() {
+        assert_eq!(
+            parse("===\n").err().unwrap(),
+            "map has no player spawn marker"
+        );
+        assert_eq!(
+            parse("P==\n==P\n").err().unwrap(),
+            "map has more than one player spawn marker (row 1, col 1 and row 2, col 3)"
+        );
+    }
+
+    #[test]
+    fn checkpoints_are_named_once() {
+        assert_eq!(
+            parse("Pa=\n=a=\n").err().unwrap(),
+            "checkpoint 'a' is defined more than once (row 1, col 2 and row 2, col 2)"
+        );
+    }
+}
@@ -1,49 +1,168 @@
+use crate::libs::Rect;
+use ::image::RgbaImage;
 use piston_window::*;
-use std::path::PathBuf;
+use serde::Deserialize;
 
-pub trait SpriteEvent {
-    fn render(&mut self, e: &Event, w: &mut PistonWindow);
+/// How a sprite is drawn over its rect.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DrawParams {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Clockwise rotation around the center of the rect, in degrees.
+    pub rotation: f64,
+    /// Color multiplied with the texture.
+    pub tint: [f32; 4],
+    pub opacity: f32,
 }
 
+impl Default for DrawParams {
+    fn default() -> DrawParams {
+        DrawParams {
+            flip_x: false,
+            flip_y: false,
+            rotation: 0.0,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            opacity: 1.0,
+        }
+    }
+}
+
+/// A region of a texture, in pixels.
+#[derive(Clone)]
+struct Frame {
+    texture: G2dTexture,
+    src: [f64; 4],
+}
+
+/// Frames cut from textures, drawn one at a time. Tests make empty ones,
+/// which need no window.
 #[derive(Clone)]
+#[cfg_attr(test, derive(Default))]
 pub struct Sprite {
-    texture: Vec<G2dTexture>,
+    frames: Vec<Frame>,
 }
 
 impl Sprite {
-    pub fn load_texture(path: PathBuf, w: &mut PistonWindow, flip: Flip) -> G2dTexture {
-        Texture::from_path(
+    /// Uploads a decoded `image` to the GPU.
+    pub fn upload(image: &RgbaImage, w: &mut PistonWindow) -> G2dTexture {
+        Texture::from_image(
             &mut w.create_texture_context(),
-            path,
-            flip,
+            image,
             &TextureSettings::new(),
         )
         .unwrap()
     }
 
-    pub fn load_texture_as_sprite(path: PathBuf, w: &mut PistonWindow, flip: Flip) -> Sprite {
-        Sprite::new(
-            Texture::from_path(
-                &mut w.create_texture_context(),
-                path,
-                flip,
-                &TextureSettings::new(),
-            )
-            .unwrap(),
-        )
+    /// A sprite showing only `src` (`[x, y, w, h]`) of `texture`.
+    pub fn from_region(texture: G2dTexture, src: [f64; 4]) -> Sprite {
+        Sprite {
+            frames: vec![Frame { texture, src }],
+        }
     }
 
-    pub fn new(texture: G2dTexture) -> Sprite {
-        Sprite {
-            texture: vec![texture],
+    /// Draws frame `idx` stretched over `rect`.
+    /// Draws frame `idx` into `rect`, or nothing if there's no such frame.
+    pub fn draw(&self, idx: usize, rect: &Rect, params: &DrawParams, c: &Context, g: &mut G2d) {
+        let frame = match self.frames.get(idx) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let [r, gr, b, a] = params.tint;
+        let [_, _, w, h] = frame.src;
+        let half = rect.scale / 2.0;
+        let sx = if params.flip_x { -1.0 } else { 1.0 };
+        let sy = if params.flip_y { -1.0 } else { 1.0 };
+        Image::new_color([r, gr, b, a * params.opacity])
+            .src_rect(frame.src)
+            .draw(
+                &frame.texture,
+                &c.draw_state,
+                c.trans(rect.x + half, rect.y + half)
+                    .rot_deg(params.rotation)
+                    .scale(sx * rect.scale / w, sy * rect.scale / h)
+                    .trans(-w / 2.0, -h / 2.0)
+                    .transform,
+                g,
+            );
+    }
+}
+
+/// Where the tiles of `tile` size (`[w, h]`) are in an image of `size`,
+/// row by row, skipping `margin` pixels around the edges and `spacing`
+/// pixels between tiles.
+pub fn regions(size: [f64; 2], tile: [f64; 2], margin: f64, spacing: f64) -> Vec<[f64; 4]> {
+    let count = |size: f64, tile: f64| {
+        ((size - 2.0 * margin + spacing) / (tile + spacing))
+            .floor()
+            .max(0.0) as usize
+    };
+    let columns = count(size[0], tile[0]);
+    let rows = count(size[1], tile[1]);
+
+    let mut regions = Vec::new();
+    for row in 0..rows {
+        for col in 0..columns {
+            regions.push([
+                margin + col as f64 * (tile[0] + spacing),
+                margin + row as f64 * (tile[1] + spacing),
+                tile[0],
+                tile[1],
+            ]);
         }
     }
+    regions
+}
+
+/// A texture made of equally sized tiles, like `assets/tileset.png`.
+pub struct Tileset {
+    texture: G2dTexture,
+    regions: Vec<[f64; 4]>,
+}
+
+impl Tileset {
+    /// Cuts `texture` into tiles where `regions` puts them. The loader
+    /// rejects tiles of no size, which would never run out.
+    pub fn slice(
+        texture: G2dTexture,
+        tile_width: f64,
+        tile_height: f64,
+        margin: f64,
+        spacing: f64,
+    ) -> Tileset {
+        assert!(
+            tile_width > 0.0 && tile_height > 0.0,
+            "can't slice into {}x{} tiles",
+            tile_width,
+            tile_height
+        );
+        let (width, height) = texture.get_size();
+        let size = [width as f64, height as f64];
+        let regions = regions(size, [tile_width, tile_height], margin, spacing);
+        Tileset { texture, regions }
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
 
-    pub fn get_texture(&self, idx: usize) -> &G2dTexture {
-        &self.texture[idx]
+    /// A sprite with every tile as a frame, in order.
+    pub fn sprites(&self) -> Sprite {
+        Sprite {
+            frames: self
+                .regions
+                .iter()
+                .map(|src| Frame {
+                    texture: self.texture.clone(),
+                    src: *src,
+                })
+                .collect(),
+        }
     }
 
-    pub fn add_texture(&mut self, texture: G2dTexture) {
-        self.texture.push(texture);
+    pub fn sprite(&self, idx: usize) -> Option<Sprite> {
+        self.regions
+            .get(idx)
+            .map(|src| Sprite::from_region(self.texture.clone(), *src))
     }
 }
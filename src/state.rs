@@ -0,0 +1,524 @@
+use piston_window::*;
+
+use crate::campaign::Campaign;
+use crate::game::Game;
+use crate::input::Action;
+use crate::world::WorldEvent;
+
+/// Seconds a fade to black takes, and as long again to fade back in.
+const FADE_TIME: f64 = 0.3;
+
+/// What a state asks of the stack after handling an action or an update.
+pub enum Transition {
+    None,
+    /// Puts a state on top, e.g. the pause overlay.
+    Push(Box<dyn State>),
+    /// Takes the state on top off, going back to the one under it.
+    Pop,
+    /// Puts a state in place of the one on top.
+    Replace(Box<dyn State>),
+    /// Starts the level over, with only a `Playing` state left.
+    Restart,
+    /// Plays stage `idx` of the campaign once it is loaded, with only a
+    /// `Playing` state left.
+    Load(usize),
+    /// Fades to black, makes the transition, then fades back in.
+    Fade(Box<Transition>),
+}
+
+/// A screen of the game, like the title or the pause overlay. Only the state
+/// on top of the stack gets actions and updates, so the ones under it are
+/// frozen.
+pub trait State {
+    /// Reacts to the player starting `action`.
+    fn action(&mut self, _action: Action, _game: &mut Game) -> Transition {
+        Transition::None
+    }
+
+    /// Moves the state on by `dt` seconds.
+    fn update(&mut self, _dt: f64, _game: &mut Game) -> Transition {
+        Transition::None
+    }
+
+    fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs);
+
+    /// Whether the states under this one still show, drawn before it.
+    fn overlay(&self) -> bool {
+        true
+    }
+}
+
+/// The states of the game, the top one being the one played.
+pub struct StateStack {
+    states: Vec<Box<dyn State>>,
+    /// Seconds into a fade, and the transition to make once the screen is
+    /// black.
+    fade: Option<(f64, Option<Transition>)>,
+    /// Whether a stage of the campaign has to be loaded.
+    load: bool,
+}
+
+impl StateStack {
+    pub fn new(states: Vec<Box<dyn State>>) -> StateStack {
+        StateStack {
+            states,
+            fade: None,
+            load: false,
+        }
+    }
+
+    /// Whether the stage `game.campaign.current` has to be loaded, which
+    /// is only told once.
+    pub fn take_load(&mut self) -> bool {
+        std::mem::take(&mut self.load)
+    }
+
+    pub fn action(&mut self, action: Action, game: &mut Game) {
+        if self.fade.is_some() {
+            return;
+        }
+        if let Some(state) = self.states.last_mut() {
+            let transition = state.action(action, game);
+            self.apply(transition, game);
+        }
+    }
+
+    pub fn update(&mut self, dt: f64, game: &mut Game) {
+        if let Some((time, then)) = self.fade.as_mut() {
+            *time += dt;
+            let time = *time;
+            if time >= FADE_TIME {
+                if let Some(transition) = then.take() {
+                    self.apply(transition, game);
+                }
+            }
+            if time >= FADE_TIME * 2.0 {
+                self.fade = None;
+            }
+            return;
+        }
+        if let Some(state) = self.states.last_mut() {
+            let transition = state.update(dt, game);
+            self.apply(transition, game);
+        }
+    }
+
+    fn apply(&mut self, transition: Transition, game: &mut Game) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(state) => self.states.push(state),
+            Transition::Pop => {
+                self.states.pop();
+            }
+            Transition::Replace(state) => {
+                self.states.pop();
+                self.states.push(state);
+            }
+            Transition::Restart => {
+                game.restart();
+                self.states = vec![Box::new(Playing)];
+            }
+            Transition::Load(idx) => {
+                game.campaign.current = idx;
+                self.load = true;
+                self.states = vec![Box::new(Playing)];
+            }
+            Transition::Fade(then) => self.fade = Some((0.0, Some(*then))),
+        }
+    }
+
+    /// Draws the top state over the states it lets show through, then the
+    /// fade over everything.
+    pub fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let bottom = self
+            .states
+            .iter()
+            .rposition(|state| !state.overlay())
+            .unwrap_or(0);
+        for state in self.states[bottom..].iter() {
+            state.render(game, c, g, glyphs);
+        }
+
+        if let Some((time, _)) = self.fade {
+            let black = 1.0 - ((time - FADE_TIME) / FADE_TIME).abs();
+            let [width, height] = c.get_view_size();
+            rectangle(
+                [0.0, 0.0, 0.0, black.clamp(0.0, 1.0) as f32],
+                [0.0, 0.0, width, height],
+                c.transform,
+                g,
+            );
+        }
+    }
+}
+
+/// Dims the screen and writes `lines` of text, each with its font size,
+/// centered in the middle of it.
+pub fn caption(lines: &[(&str, u32)], c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+    let [width, height] = c.get_view_size();
+    rectangle(
+        [0.0, 0.0, 0.0, 0.5],
+        [0.0, 0.0, width, height],
+        c.transform,
+        g,
+    );
+
+    let total: f64 = lines.iter().map(|(_, size)| *size as f64 * 1.5).sum();
+    let mut y = (height - total) / 2.0;
+    for (line, size) in lines.iter() {
+        y += *size as f64 * 1.5;
+        let text_width = glyphs.width(*size, line).unwrap_or(0.0);
+        text::Text::new_color([1.0, 1.0, 1.0, 1.0], *size)
+            .round()
+            .draw(
+                line,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans((width - text_width) / 2.0, y),
+                g,
+            )
+            .unwrap();
+    }
+}
+
+/// Shown over the level before it starts.
+pub struct Title;
+
+impl State for Title {
+    fn action(&mut self, action: Action, game: &mut Game) -> Transition {
+        match action {
+            Action::Jump => Transition::Replace(Box::new(LevelSelect::new(&game.campaign, false))),
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&self, _game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        caption(
+            &[("2D Platformer", 48), ("Press jump to start", 24)],
+            c,
+            g,
+            glyphs,
+        );
+    }
+}
+
+/// Lists the stages of the campaign to pick one to play, those not
+/// unlocked yet greyed out.
+pub struct LevelSelect {
+    selected: usize,
+    /// Whether the stage behind this screen was played, rather than only
+    /// loaded.
+    played: bool,
+}
+
+impl LevelSelect {
+    pub fn new(campaign: &Campaign, played: bool) -> LevelSelect {
+        LevelSelect {
+            selected: campaign.current,
+            played,
+        }
+    }
+}
+
+impl State for LevelSelect {
+    fn action(&mut self, action: Action, game: &mut Game) -> Transition {
+        let campaign = &game.campaign;
+        match action {
+            Action::MoveLeft => self.selected = self.selected.saturating_sub(1),
+            Action::MoveRight if campaign.unlocked(self.selected + 1) => {
+                self.selected = (self.selected + 1).min(campaign.stages.len() - 1);
+            }
+            // the level behind this screen is ready to play unless it was
+            // played already, which it then starts over
+            Action::Jump if self.selected == campaign.current && !self.played => {
+                return Transition::Fade(Box::new(Transition::Pop));
+            }
+            Action::Jump if self.selected == campaign.current => {
+                return Transition::Fade(Box::new(Transition::Restart));
+            }
+            Action::Jump => return Transition::Fade(Box::new(Transition::Load(self.selected))),
+            _ => {}
+        }
+        Transition::None
+    }
+
+    fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let campaign = &game.campaign;
+        let stages: Vec<String> = campaign
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(idx, stage)| {
+                let status = if campaign.cleared(idx) {
+                    "  (cleared)"
+                } else if !campaign.unlocked(idx) {
+                    "  (locked)"
+                } else {
+                    ""
+                };
+                if idx == self.selected {
+                    format!("> {}{} <", stage.name, status)
+                } else {
+                    format!("{}{}", stage.name, status)
+                }
+            })
+            .collect();
+        let mut lines = vec![("Select a level", 48)];
+        lines.extend(stages.iter().map(|stage| (stage.as_str(), 24)));
+        lines.push(("Left and right to choose, jump to play", 24));
+        caption(&lines, c, g, glyphs);
+    }
+}
+
+/// The level being played.
+pub struct Playing;
+
+impl State for Playing {
+    fn action(&mut self, action: Action, game: &mut Game) -> Transition {
+        match action {
+            Action::Pause => Transition::Push(Box::new(Paused)),
+            Action::Respawn => {
+                game.respawn = true;
+                Transition::None
+            }
+            _ => Transition::None,
+        }
+    }
+
+    fn update(&mut self, dt: f64, game: &mut Game) -> Transition {
+        for event in game.advance(dt) {
+            match event {
+                WorldEvent::Died if game.world.score.lives == 0 => {
+                    return Transition::Push(Box::new(GameOver));
+                }
+                WorldEvent::Cleared => {
+                    game.campaign.complete();
+                    return Transition::Push(Box::new(LevelClear));
+                }
+                _ => {}
+            }
+        }
+        Transition::None
+    }
+
+    fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        game.render(c, g, glyphs);
+    }
+
+    fn overlay(&self) -> bool {
+        false
+    }
+}
+
+/// Shown over the level, frozen until pause is pressed again.
+pub struct Paused;
+
+impl State for Paused {
+    fn action(&mut self, action: Action, _game: &mut Game) -> Transition {
+        match action {
+            Action::Pause => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&self, _game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        caption(&[("Paused", 48)], c, g, glyphs);
+    }
+}
+
+/// Shown once the player runs out of lives.
+pub struct GameOver;
+
+impl State for GameOver {
+    fn action(&mut self, action: Action, _game: &mut Game) -> Transition {
+        match action {
+            Action::Jump => Transition::Fade(Box::new(Transition::Restart)),
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let score = format!("Score {}", game.world.score.points);
+        caption(
+            &[
+                ("Game Over", 48),
+                (&score, 24),
+                ("Press jump to try again", 24),
+            ],
+            c,
+            g,
+            glyphs,
+        );
+    }
+}
+
+/// Shown once the player reaches the goal, until jump moves on to the next
+/// stage, or back to the level select screen after the last.
+pub struct LevelClear;
+
+impl State for LevelClear {
+    fn action(&mut self, action: Action, game: &mut Game) -> Transition {
+        match action {
+            Action::Jump => Transition::Fade(Box::new(match game.campaign.next() {
+                Some(idx) => Transition::Load(idx),
+                None => Transition::Replace(Box::new(LevelSelect::new(&game.campaign, true))),
+            })),
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&self, game: &Game, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let score = &game.world.score;
+        let time = score.time as u32;
+        let summary = format!(
+            "Score {}   Time {}:{:02}",
+            score.points,
+            time / 60,
+            time % 60
+        );
+        let next = match game.campaign.next() {
+            Some(_) => "Press jump for the next level",
+            None => "Press jump to choose a level",
+        };
+        caption(
+            &[("Level Clear!", 48), (&summary, 24), (next, 24)],
+            c,
+            g,
+            glyphs,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::Stage;
+    use crate::game::tests::game;
+    use crate::player::PlayerPhysics;
+    use crate::replay::{Recorder, Replay};
+    use std::fs;
+    use std::path::PathBuf;
+
+    const STEP: f64 = 1.0 / 60.0;
+
+    /// Stages of `maps`, each named after its map.
+    fn stages(maps: &[&str]) -> Vec<Stage> {
+        maps.iter()
+            .map(|map| Stage {
+                name: (*map).to_owned(),
+                map: PathBuf::from(map),
+            })
+            .collect()
+    }
+
+    fn run(states: &mut StateStack, game: &mut Game, seconds: f64) {
+        for _ in 0..(seconds / STEP).round() as usize {
+            states.update(STEP, game);
+        }
+    }
+
+    #[test]
+    fn pausing_freezes_the_game() {
+        let mut game = game("=====\n==P==\n=====\n=====\n11111");
+        let mut states = StateStack::new(vec![Box::new(Playing)]);
+        run(&mut states, &mut game, 0.1);
+        let falling = game.world.player.rect.y;
+        let time = game.world.score.time;
+
+        states.action(Action::Pause, &mut game);
+        run(&mut states, &mut game, 1.0);
+        assert_eq!(game.world.player.rect.y, falling);
+        assert_eq!(game.world.score.time, time);
+
+        states.action(Action::Pause, &mut game);
+        run(&mut states, &mut game, 0.1);
+        assert!(game.world.player.rect.y > falling);
+    }
+
+    #[test]
+    fn running_out_of_lives_ends_the_game_until_jump_restarts_it() {
+        let mut game = game("==P==\n=====");
+        let mut states = StateStack::new(vec![Box::new(Playing)]);
+        game.world.score.lives = 1;
+        run(&mut states, &mut game, 2.0);
+        assert_eq!(states.states.len(), 2);
+        assert_eq!(game.world.score.lives, 0);
+
+        // everything under the game over screen stays as it was
+        let time = game.world.score.time;
+        run(&mut states, &mut game, 1.0);
+        assert_eq!(game.world.score.time, time);
+
+        states.action(Action::Jump, &mut game);
+        run(&mut states, &mut game, FADE_TIME * 2.0 + STEP);
+        assert!(states.fade.is_none());
+        assert_eq!(states.states.len(), 1);
+        assert_eq!(game.world.score.lives, 3);
+    }
+
+    #[test]
+    fn reaching_the_goal_moves_on_to_the_next_stage() {
+        let mut game = game("=====\nP==G=\n11111");
+        game.campaign = Campaign::new(stages(&["assets/map.txt", "assets/map2.txt"]), None);
+        let mut states = StateStack::new(vec![Box::new(Playing)]);
+        let right = ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(Key::Right),
+            scancode: None,
+        };
+        game.input
+            .event(&Event::Input(piston_window::Input::Button(right), None));
+        run(&mut states, &mut game, 2.0);
+        assert_eq!(states.states.len(), 2);
+        assert!(game.campaign.cleared(0));
+
+        states.action(Action::Jump, &mut game);
+        run(&mut states, &mut game, FADE_TIME + STEP);
+        assert!(states.take_load());
+        assert!(!states.take_load());
+        assert_eq!(game.campaign.current, 1);
+        assert_eq!(states.states.len(), 1);
+    }
+
+    #[test]
+    fn only_unlocked_stages_can_be_picked() {
+        let mut game = game("==P==\n11111");
+        game.campaign = Campaign::new(stages(&["a", "b"]), None);
+        let mut select = LevelSelect::new(&game.campaign, true);
+        select.action(Action::MoveRight, &mut game);
+        assert_eq!(select.selected, 0);
+
+        game.campaign.complete();
+        select.action(Action::MoveRight, &mut game);
+        select.action(Action::MoveRight, &mut game);
+        assert_eq!(select.selected, 1);
+        assert!(matches!(
+            select.action(Action::Jump, &mut game),
+            Transition::Fade(then) if matches!(*then, Transition::Load(1))
+        ));
+    }
+
+    #[test]
+    fn recording_goes_on_from_the_title_into_the_level() {
+        let mut game = game("=====\n==P==\n11111");
+        game.campaign = Campaign::new(stages(&["assets/map.txt"]), None);
+        let path = std::env::temp_dir().join(format!("record-{}.json", std::process::id()));
+        let replay = Replay::new(PathBuf::from("assets/map.txt"), PlayerPhysics::default());
+        game.recorder = Some(Recorder::new(replay, path.clone()));
+        let mut states = StateStack::new(vec![Box::new(Playing), Box::new(Title)]);
+
+        states.action(Action::Jump, &mut game);
+        states.action(Action::Jump, &mut game);
+        run(&mut states, &mut game, FADE_TIME * 2.0 + 1.0);
+        assert_eq!(states.states.len(), 1);
+        let recorder = game.recorder.as_ref().unwrap();
+        assert!(recorder.replay.tick(0).is_some());
+
+        // a fresh world is recorded from its start
+        game.restart();
+        let recorder = game.recorder.as_ref().unwrap();
+        assert!(recorder.replay.tick(0).is_none());
+        drop(game);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,986 @@
+//! Importer for maps made with the Tiled editor, in either its JSON
+//! (`.tmj`) or XML (`.tmx`) format.
+//!
+//! Tiles get their sprite from a `sprite` custom property, falling back to
+//! the file stem of the tile's own image, and are solid when their `solid`
+//! property (or else their layer's) is true. Tiles of a tileset cut from a
+//! single image are named `<tileset>:<index>` and drawn from that image,
+//! which needs a tile size above 0. A `bump` property of `break`, or of
+//! `item` along with `spawn` and `used`, sets what the tile does when hit
+//! from below. Objects of type `spawn`, `checkpoint` and `trigger` become
+//! spawn markers and triggers, an object of type `goal` ends the level once
+//! the player reaches it, objects of type `collectible` place an item of
+//! their `kind` property, and objects of type `enemy` place a `walker`,
+//! `hopper` or `flyer` by their `kind`, flyers patrolling `patrol_x`,
+//! `patrol_y` tiles away.
+
+use crate::legend::{BumpBehavior, EnemyKind};
+use crate::level::{Collectible, EnemySpawn, Level, Tile, TilesetImage, Trigger, TriggerAction};
+use crate::spawn::{SpawnCollector, SpawnError, SpawnPoint};
+use crate::sprite::DrawParams;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const GID_MASK: u32 = 0x0fff_ffff;
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+    Xml(PathBuf, roxmltree::Error),
+    Format(PathBuf, String),
+    Spawn(PathBuf, SpawnError),
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            TiledError::Json(path, err) => write!(f, "{}: {}", path.display(), err),
+            TiledError::Xml(path, err) => write!(f, "{}: {}", path.display(), err),
+            TiledError::Format(path, msg) => write!(f, "{}: {}", path.display(), msg),
+            TiledError::Spawn(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+pub fn is_tiled_map(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("tmj") | Some("tmx") | Some("json")
+    )
+}
+
+pub fn load(path: &Path) -> Result<Level, TiledError> {
+    let map = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") => xml::map(path)?,
+        _ => json::map(path)?,
+    };
+    map.into_level()
+        .map_err(|err| TiledError::Spawn(path.to_owned(), err))
+}
+
+/// Custom properties of a tile or layer that the game understands.
+#[derive(Clone, Default)]
+struct Properties {
+    sprite: Option<String>,
+    solid: Option<bool>,
+    checkpoint: Option<String>,
+    /// Item a collectible object places, e.g. `coin`, or enemy an enemy
+    /// object places, and how far a flyer patrols, in tiles.
+    kind: Option<String>,
+    patrol_x: Option<f64>,
+    patrol_y: Option<f64>,
+    /// What the tile does when bumped, and the item and sprite it uses.
+    bump: Option<String>,
+    spawn: Option<String>,
+    used: Option<String>,
+    /// Camera moves of a trigger: where to pan to, in tiles, and how.
+    pan_x: Option<f64>,
+    pan_y: Option<f64>,
+    zoom: Option<f64>,
+    duration: Option<f64>,
+    hold: Option<f64>,
+}
+
+impl Properties {
+    fn set(&mut self, name: &str, value: PropertyValue) {
+        match (name, value) {
+            ("sprite", PropertyValue::Str(s)) => self.sprite = Some(s),
+            ("solid", PropertyValue::Bool(b)) => self.solid = Some(b),
+            ("checkpoint", PropertyValue::Str(s)) => self.checkpoint = Some(s),
+            ("kind", PropertyValue::Str(s)) => self.kind = Some(s),
+            ("bump", PropertyValue::Str(s)) => self.bump = Some(s),
+            ("spawn", PropertyValue::Str(s)) => self.spawn = Some(s),
+            ("used", PropertyValue::Str(s)) => self.used = Some(s),
+            ("patrol_x", PropertyValue::Number(n)) => self.patrol_x = Some(n),
+            ("patrol_y", PropertyValue::Number(n)) => self.patrol_y = Some(n),
+            ("pan_x", PropertyValue::Number(n)) => self.pan_x = Some(n),
+            ("pan_y", PropertyValue::Number(n)) => self.pan_y = Some(n),
+            ("zoom", PropertyValue::Number(n)) => self.zoom = Some(n),
+            ("duration", PropertyValue::Number(n)) => self.duration = Some(n),
+            ("hold", PropertyValue::Number(n)) => self.hold = Some(n),
+            _ => {}
+        }
+    }
+
+    /// What a tile with these properties does when bumped, or why it can't
+    /// tell.
+    fn bump(&self) -> Result<BumpBehavior, String> {
+        match (self.bump.as_deref(), &self.spawn, &self.used) {
+            (None, ..) => Ok(BumpBehavior::None),
+            (Some("break"), ..) => Ok(BumpBehavior::Break),
+            (Some("item"), Some(spawn), Some(used)) => Ok(BumpBehavior::Item {
+                spawn: spawn.clone(),
+                used: used.clone(),
+            }),
+            (Some("item"), ..) => Err("item bump needs spawn and used properties".to_owned()),
+            (Some(bump), ..) => Err(format!("unknown bump '{}'", bump)),
+        }
+    }
+
+    /// The enemy an enemy object with these properties places, or why it
+    /// can't tell.
+    fn enemy(&self) -> Result<EnemyKind, String> {
+        match self.kind.as_deref() {
+            Some("walker") => Ok(EnemyKind::Walker),
+            Some("hopper") => Ok(EnemyKind::Hopper),
+            Some("flyer") => Ok(EnemyKind::Flyer {
+                dx: self.patrol_x.unwrap_or(0.0),
+                dy: self.patrol_y.unwrap_or(0.0),
+            }),
+            Some(kind) => Err(format!("unknown enemy '{}'", kind)),
+            None => Err("no kind property".to_owned()),
+        }
+    }
+
+    /// What a trigger object with these properties does: reach a checkpoint,
+    /// pan the camera to `pan_x`, `pan_y`, or just zoom it.
+    fn action(&self) -> Option<TriggerAction> {
+        let duration = self.duration.unwrap_or(1.0);
+        match (&self.checkpoint, self.pan_x, self.pan_y, self.zoom) {
+            (Some(name), ..) => Some(TriggerAction::Checkpoint(name.clone())),
+            (None, Some(x), Some(y), zoom) => Some(TriggerAction::Pan {
+                x,
+                y,
+                zoom,
+                duration,
+                hold: self.hold.unwrap_or(1.0),
+            }),
+            (None, None, None, Some(zoom)) => Some(TriggerAction::Zoom { zoom, duration }),
+            _ => None,
+        }
+    }
+}
+
+enum PropertyValue {
+    Str(String),
+    Bool(bool),
+    Number(f64),
+    Other,
+}
+
+struct Tileset {
+    first_gid: u32,
+    tiles: BTreeMap<u32, Properties>,
+    image: Option<TilesetImage>,
+}
+
+struct MapObject {
+    name: String,
+    kind: String,
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+    properties: Properties,
+}
+
+enum Layer {
+    Tiles {
+        name: String,
+        width: usize,
+        data: Vec<u32>,
+        properties: Box<Properties>,
+    },
+    Objects(Vec<MapObject>),
+}
+
+struct Map {
+    width: usize,
+    height: usize,
+    tile_width: f64,
+    tile_height: f64,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+}
+
+impl Map {
+    fn tile(&self, gid: u32) -> Option<Properties> {
+        let tileset = self
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)?;
+        let id = gid - tileset.first_gid;
+        let mut properties = tileset.tiles.get(&id).cloned().unwrap_or_default();
+        if properties.sprite.is_none() {
+            properties.sprite = tileset
+                .image
+                .as_ref()
+                .map(|image| format!("{}:{}", image.name, id));
+        }
+        Some(properties)
+    }
+
+    fn into_level(self) -> Result<Level, SpawnError> {
+        let mut tiles = Vec::new();
+        let mut collectibles = Vec::new();
+        let mut enemies = Vec::new();
+        let mut spawns = SpawnCollector::default();
+        let mut triggers = Vec::new();
+        let mut warnings = Vec::new();
+
+        for layer in self.layers.iter() {
+            match layer {
+                Layer::Tiles {
+                    name,
+                    width,
+                    data,
+                    properties,
+                } => {
+                    for (i, raw) in data.iter().enumerate() {
+                        let gid = raw & GID_MASK;
+                        if gid == 0 {
+                            continue;
+                        }
+                        let (row, col) = (i / width, i % width);
+                        let tile = self.tile(gid).unwrap_or_default();
+                        let bump = tile.bump().unwrap_or_else(|err| {
+                            warnings.push(format!(
+                                "layer '{}', row {}, col {}: {}",
+                                name,
+                                row + 1,
+                                col + 1,
+                                err
+                            ));
+                            BumpBehavior::None
+                        });
+                        match tile.sprite {
+                            Some(sprite) => tiles.push(Tile {
+                                row,
+                                col,
+                                sprite: Some(sprite),
+                                solid: tile.solid.or(properties.solid).unwrap_or(false),
+                                draw: draw_params(*raw),
+                                bump,
+                            }),
+                            None => warnings.push(format!(
+                                "layer '{}', row {}, col {}: tile {} has no sprite",
+                                name,
+                                row + 1,
+                                col + 1,
+                                gid
+                            )),
+                        }
+                    }
+                }
+                Layer::Objects(objects) => {
+                    for object in objects.iter() {
+                        let x = object.x / self.tile_width;
+                        let y = object.y / self.tile_height;
+                        let point = SpawnPoint {
+                            row: y.max(0.0) as usize,
+                            col: x.max(0.0) as usize,
+                        };
+                        let area = |action| Trigger {
+                            x,
+                            y,
+                            w: (object.w / self.tile_width).max(1.0),
+                            h: (object.h / self.tile_height).max(1.0),
+                            action,
+                        };
+                        match object.kind.as_str() {
+                            "spawn" => spawns.player(point)?,
+                            "checkpoint" => {
+                                spawns.checkpoint(&object.name, point)?;
+                                triggers.push(area(TriggerAction::Checkpoint(object.name.clone())));
+                            }
+                            "goal" => triggers.push(area(TriggerAction::Goal)),
+                            "trigger" => match object.properties.action() {
+                                Some(action) => triggers.push(area(action)),
+                                None => warnings.push(format!(
+                                    "trigger '{}' has no action property",
+                                    object.name
+                                )),
+                            },
+                            "collectible" => match &object.properties.kind {
+                                Some(kind) => collectibles.push(Collectible {
+                                    row: point.row,
+                                    col: point.col,
+                                    kind: kind.clone(),
+                                }),
+                                None => warnings.push(format!(
+                                    "collectible '{}' has no kind property",
+                                    object.name
+                                )),
+                            },
+                            "enemy" => match object.properties.enemy() {
+                                Ok(kind) => enemies.push(EnemySpawn {
+                                    row: point.row,
+                                    col: point.col,
+                                    kind,
+                                }),
+                                Err(err) => {
+                                    warnings.push(format!("enemy '{}': {}", object.name, err))
+                                }
+                            },
+                            kind => warnings.push(format!(
+                                "object '{}' has unknown type '{}'",
+                                object.name, kind
+                            )),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Level {
+            width: self.width,
+            height: self.height,
+            tiles,
+            collectibles,
+            enemies,
+            spawns: spawns.finish()?,
+            triggers,
+            tilesets: self
+                .tilesets
+                .into_iter()
+                .filter_map(|tileset| tileset.image)
+                .collect(),
+            warnings,
+        })
+    }
+}
+
+/// Turns the flip flags Tiled stores in a gid into a rotation and an
+/// horizontal flip, which together cover every combination.
+fn draw_params(gid: u32) -> DrawParams {
+    let h = gid & FLIPPED_HORIZONTALLY != 0;
+    let v = gid & FLIPPED_VERTICALLY != 0;
+    let d = gid & FLIPPED_DIAGONALLY != 0;
+    let (rotation, flip_x) = match (h, v, d) {
+        (false, false, false) => (0.0, false),
+        (true, false, false) => (0.0, true),
+        (true, false, true) => (90.0, false),
+        (true, true, true) => (90.0, true),
+        (true, true, false) => (180.0, false),
+        (false, true, false) => (180.0, true),
+        (false, true, true) => (270.0, false),
+        (false, false, true) => (270.0, true),
+    };
+    DrawParams {
+        flip_x,
+        rotation,
+        ..DrawParams::default()
+    }
+}
+
+/// Sprite key of a tile that has its own image, e.g. `brick` for `brick.png`.
+fn image_sprite(image: &str) -> Option<String> {
+    Path::new(image)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.to_owned())
+}
+
+/// A tile layer of `width` tiles a row, which its tiles can't be placed
+/// without.
+fn tile_layer(
+    path: &Path,
+    name: String,
+    width: usize,
+    data: Vec<u32>,
+    properties: Properties,
+) -> Result<Layer, TiledError> {
+    if width == 0 && !data.is_empty() {
+        return Err(TiledError::Format(
+            path.to_owned(),
+            format!("layer '{}' has no width", name),
+        ));
+    }
+    Ok(Layer::Tiles {
+        name,
+        width,
+        data,
+        properties: Box::new(properties),
+    })
+}
+
+/// The size of the tiles the image of tileset `name` is cut into, which
+/// can't be cut into tiles of no size.
+fn tile_size(
+    path: &Path,
+    name: &str,
+    width: Option<f64>,
+    height: Option<f64>,
+) -> Result<(f64, f64), TiledError> {
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0.0 && height > 0.0 => Ok((width, height)),
+        _ => Err(TiledError::Format(
+            path.to_owned(),
+            format!(
+                "tileset '{}' needs a tilewidth and tileheight above 0",
+                name
+            ),
+        )),
+    }
+}
+
+fn read(path: &Path) -> Result<String, TiledError> {
+    fs::read_to_string(path).map_err(|err| TiledError::Io(path.to_owned(), err))
+}
+
+mod json {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct JsonProperty {
+        name: String,
+        value: serde_json::Value,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonTile {
+        id: u32,
+        image: Option<String>,
+        #[serde(default)]
+        properties: Vec<JsonProperty>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonTileset {
+        firstgid: Option<u32>,
+        source: Option<String>,
+        #[serde(default)]
+        name: String,
+        image: Option<String>,
+        tilewidth: Option<f64>,
+        tileheight: Option<f64>,
+        #[serde(default)]
+        margin: f64,
+        #[serde(default)]
+        spacing: f64,
+        #[serde(default)]
+        tiles: Vec<JsonTile>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonObject {
+        #[serde(default)]
+        name: String,
+        #[serde(default, alias = "class")]
+        r#type: String,
+        x: f64,
+        y: f64,
+        #[serde(default)]
+        width: f64,
+        #[serde(default)]
+        height: f64,
+        gid: Option<u32>,
+        #[serde(default)]
+        properties: Vec<JsonProperty>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonLayer {
+        #[serde(default)]
+        name: String,
+        r#type: String,
+        #[serde(default)]
+        width: usize,
+        data: Option<serde_json::Value>,
+        encoding: Option<String>,
+        #[serde(default)]
+        objects: Vec<JsonObject>,
+        #[serde(default)]
+        layers: Vec<JsonLayer>,
+        #[serde(default)]
+        properties: Vec<JsonProperty>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonMap {
+        width: usize,
+        height: usize,
+        tilewidth: f64,
+        tileheight: f64,
+        #[serde(default)]
+        tilesets: Vec<JsonTileset>,
+        layers: Vec<JsonLayer>,
+    }
+
+    fn parse<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, TiledError> {
+        serde_json::from_str(&read(path)?).map_err(|err| TiledError::Json(path.to_owned(), err))
+    }
+
+    fn properties(list: &[JsonProperty]) -> Properties {
+        let mut properties = Properties::default();
+        for property in list.iter() {
+            let value = match &property.value {
+                serde_json::Value::String(s) => PropertyValue::Str(s.clone()),
+                serde_json::Value::Bool(b) => PropertyValue::Bool(*b),
+                serde_json::Value::Number(n) => n
+                    .as_f64()
+                    .map_or(PropertyValue::Other, PropertyValue::Number),
+                _ => PropertyValue::Other,
+            };
+            properties.set(&property.name, value);
+        }
+        properties
+    }
+
+    /// Reads `tileset`, found in the file at `path`.
+    fn tileset(path: &Path, first_gid: u32, tileset: &JsonTileset) -> Result<Tileset, TiledError> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let tiles = tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                let mut props = properties(&tile.properties);
+                if props.sprite.is_none() {
+                    props.sprite = tile.image.as_deref().and_then(image_sprite);
+                }
+                (tile.id, props)
+            })
+            .collect();
+        let image = match &tileset.image {
+            Some(image) => {
+                let (tile_width, tile_height) =
+                    tile_size(path, &tileset.name, tileset.tilewidth, tileset.tileheight)?;
+                Some(TilesetImage {
+                    name: tileset.name.clone(),
+                    image: dir.join(image),
+                    tile_width,
+                    tile_height,
+                    margin: tileset.margin,
+                    spacing: tileset.spacing,
+                })
+            }
+            None => None,
+        };
+        Ok(Tileset {
+            first_gid,
+            tiles,
+            image,
+        })
+    }
+
+    pub fn tileset_file(path: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+        let external: JsonTileset = parse(path)?;
+        tileset(path, first_gid, &external)
+    }
+
+    fn layers(path: &Path, list: &[JsonLayer], out: &mut Vec<Layer>) -> Result<(), TiledError> {
+        for layer in list.iter() {
+            match layer.r#type.as_str() {
+                "tilelayer" => {
+                    if layer.encoding.as_deref().unwrap_or("csv") != "csv" {
+                        return Err(TiledError::Format(
+                            path.to_owned(),
+                            format!("layer '{}' must be saved with CSV encoding", layer.name),
+                        ));
+                    }
+                    let data = layer
+                        .data
+                        .clone()
+                        .map(serde_json::from_value::<Vec<u32>>)
+                        .transpose()
+                        .map_err(|err| TiledError::Json(path.to_owned(), err))?
+                        .unwrap_or_default();
+                    out.push(tile_layer(
+                        path,
+                        layer.name.clone(),
+                        layer.width,
+                        data,
+                        properties(&layer.properties),
+                    )?);
+                }
+                "objectgroup" => out.push(Layer::Objects(
+                    layer
+                        .objects
+                        .iter()
+                        .map(|object| MapObject {
+                            name: object.name.clone(),
+                            kind: object.r#type.clone(),
+                            x: object.x,
+                            // tile objects are anchored at their bottom left corner
+                            y: if object.gid.is_some() {
+                                object.y - object.height
+                            } else {
+                                object.y
+                            },
+                            w: object.width,
+                            h: object.height,
+                            properties: properties(&object.properties),
+                        })
+                        .collect(),
+                )),
+                "group" => layers(path, &layer.layers, out)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn map(path: &Path) -> Result<Map, TiledError> {
+        let map: JsonMap = parse(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut tilesets = Vec::new();
+        for entry in map.tilesets.iter() {
+            let first_gid = entry.firstgid.unwrap_or(1);
+            match &entry.source {
+                Some(source) => {
+                    let source = dir.join(source);
+                    if source.extension().and_then(|ext| ext.to_str()) == Some("tsx") {
+                        tilesets.push(xml::tileset(&source, first_gid)?);
+                    } else {
+                        tilesets.push(tileset_file(&source, first_gid)?);
+                    }
+                }
+                None => tilesets.push(tileset(path, first_gid, entry)?),
+            }
+        }
+
+        let mut out = Vec::new();
+        layers(path, &map.layers, &mut out)?;
+
+        Ok(Map {
+            width: map.width,
+            height: map.height,
+            tile_width: map.tilewidth,
+            tile_height: map.tileheight,
+            tilesets,
+            layers: out,
+        })
+    }
+}
+
+mod xml {
+    use super::*;
+    use roxmltree::{Document, Node};
+
+    fn format_error(path: &Path, msg: String) -> TiledError {
+        TiledError::Format(path.to_owned(), msg)
+    }
+
+    fn attr<T: std::str::FromStr>(path: &Path, node: Node, name: &str) -> Result<T, TiledError> {
+        node.attribute(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                format_error(
+                    path,
+                    format!("<{}> is missing a valid '{}'", node.tag_name().name(), name),
+                )
+            })
+    }
+
+    fn attr_or<T: std::str::FromStr>(node: Node, name: &str, default: T) -> T {
+        node.attribute(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+        node.children().find(|child| child.has_tag_name(name))
+    }
+
+    fn properties(node: Node) -> Properties {
+        let mut properties = Properties::default();
+        if let Some(list) = child(node, "properties") {
+            for property in list.children().filter(|p| p.has_tag_name("property")) {
+                let value = property.attribute("value").unwrap_or_default();
+                let value = match property.attribute("type").unwrap_or("string") {
+                    "string" | "file" => PropertyValue::Str(value.to_owned()),
+                    "bool" => PropertyValue::Bool(value == "true"),
+                    "int" | "float" => value
+                        .parse()
+                        .map_or(PropertyValue::Other, PropertyValue::Number),
+                    _ => PropertyValue::Other,
+                };
+                properties.set(property.attribute("name").unwrap_or_default(), value);
+            }
+        }
+        properties
+    }
+
+    fn tiles(node: Node) -> BTreeMap<u32, Properties> {
+        node.children()
+            .filter(|tile| tile.has_tag_name("tile"))
+            .filter_map(|tile| {
+                let id = tile.attribute("id")?.parse().ok()?;
+                let mut props = properties(tile);
+                if props.sprite.is_none() {
+                    props.sprite = child(tile, "image")
+                        .and_then(|image| image.attribute("source"))
+                        .and_then(image_sprite);
+                }
+                Some((id, props))
+            })
+            .collect()
+    }
+
+    /// Reads the tileset `node` of the file at `path`.
+    fn embedded_tileset(path: &Path, node: Node, first_gid: u32) -> Result<Tileset, TiledError> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let name = node.attribute("name").unwrap_or_default();
+        let source = child(node, "image").and_then(|image| image.attribute("source"));
+        let image = match source {
+            Some(image) => {
+                let size = |attr: &str| node.attribute(attr).and_then(|value| value.parse().ok());
+                let (tile_width, tile_height) =
+                    tile_size(path, name, size("tilewidth"), size("tileheight"))?;
+                Some(TilesetImage {
+                    name: name.to_owned(),
+                    image: dir.join(image),
+                    tile_width,
+                    tile_height,
+                    margin: attr_or(node, "margin", 0.0),
+                    spacing: attr_or(node, "spacing", 0.0),
+                })
+            }
+            None => None,
+        };
+        Ok(Tileset {
+            first_gid,
+            tiles: tiles(node),
+            image,
+        })
+    }
+
+    pub fn tileset(path: &Path, first_gid: u32) -> Result<Tileset, TiledError> {
+        let content = read(path)?;
+        let doc = Document::parse(&content).map_err(|err| TiledError::Xml(path.to_owned(), err))?;
+        embedded_tileset(path, doc.root_element(), first_gid)
+    }
+
+    fn layers(path: &Path, node: Node, out: &mut Vec<Layer>) -> Result<(), TiledError> {
+        for layer in node.children().filter(|n| n.is_element()) {
+            match layer.tag_name().name() {
+                "layer" => {
+                    let name = layer.attribute("name").unwrap_or_default().to_owned();
+                    let data = child(layer, "data").ok_or_else(|| {
+                        format_error(path, format!("layer '{}' has no data", name))
+                    })?;
+                    if data.attribute("encoding") != Some("csv") {
+                        return Err(format_error(
+                            path,
+                            format!("layer '{}' must be saved with CSV encoding", name),
+                        ));
+                    }
+                    let data = data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|gid| gid.trim().parse::<u32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| {
+                            format_error(path, format!("layer '{}': bad tile: {}", name, err))
+                        })?;
+                    let width = attr(path, layer, "width")?;
+                    out.push(tile_layer(path, name, width, data, properties(layer))?);
+                }
+                "objectgroup" => {
+                    let mut objects = Vec::new();
+                    for object in layer.children().filter(|n| n.has_tag_name("object")) {
+                        let h = attr_or(object, "height", 0.0);
+                        let y: f64 = attr(path, object, "y")?;
+                        objects.push(MapObject {
+                            name: object.attribute("name").unwrap_or_default().to_owned(),
+                            kind: object
+                                .attribute("type")
+                                .or_else(|| object.attribute("class"))
+                                .unwrap_or_default()
+                                .to_owned(),
+                            x: attr(path, object, "x")?,
+                            // tile objects are anchored at their bottom left corner
+                            y: if object.has_attribute("gid") {
+                                y - h
+                            } else {
+                                y
+                            },
+                            w: attr_or(object, "width", 0.0),
+                            h,
+                            properties: properties(object),
+                        });
+                    }
+                    out.push(Layer::Objects(objects));
+                }
+                "group" => layers(path, layer, out)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn map(path: &Path) -> Result<Map, TiledError> {
+        let content = read(path)?;
+        let doc = Document::parse(&content).map_err(|err| TiledError::Xml(path.to_owned(), err))?;
+        let root = doc.root_element();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut tilesets = Vec::new();
+        for entry in root.children().filter(|n| n.has_tag_name("tileset")) {
+            let first_gid = attr(path, entry, "firstgid")?;
+            match entry.attribute("source") {
+                Some(source) => {
+                    let source = dir.join(source);
+                    if source.extension().and_then(|ext| ext.to_str()) == Some("tsx") {
+                        tilesets.push(tileset(&source, first_gid)?);
+                    } else {
+                        tilesets.push(json::tileset_file(&source, first_gid)?);
+                    }
+                }
+                None => tilesets.push(embedded_tileset(path, entry, first_gid)?),
+            }
+        }
+
+        let mut out = Vec::new();
+        layers(path, root, &mut out)?;
+
+        Ok(Map {
+            width: attr(path, root, "width")?,
+            height: attr(path, root, "height")?,
+            tile_width: attr(path, root, "tilewidth")?,
+            tile_height: attr(path, root, "tileheight")?,
+            tilesets,
+            layers: out,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new("tests/fixtures/tiled").join(name)
+    }
+
+    fn tile(level: &Level, row: usize, col: usize) -> &Tile {
+        let found = level.tiles.iter().find(|t| t.row == row && t.col == col);
+        found.unwrap_or_else(|| panic!("no tile at row {}, col {}", row, col))
+    }
+
+    /// What both the `.tmj` and the `.tmx` fixture describe.
+    fn check(level: &Level) {
+        assert_eq!((level.width, level.height), (4, 3));
+        assert_eq!(level.tiles.len(), 5);
+
+        // tiles of the external tileset, named by property or image
+        let question = tile(level, 1, 1);
+        assert_eq!(question.sprite.as_deref(), Some("question"));
+        assert!(question.solid);
+        assert_eq!(
+            question.bump,
+            BumpBehavior::Item {
+                spawn: "coin".to_owned(),
+                used: "used_block".to_owned(),
+            }
+        );
+        let brick = tile(level, 2, 0);
+        assert_eq!(brick.sprite.as_deref(), Some("brick"));
+        assert_eq!(brick.bump, BumpBehavior::Break);
+
+        // tiles cut from the embedded tileset's image, solid like their
+        // layer unless they say otherwise, each flipped another way
+        let flipped = tile(level, 2, 1);
+        assert_eq!(flipped.sprite.as_deref(), Some("terrain:0"));
+        assert!(flipped.solid);
+        assert!(flipped.draw.flip_x);
+        assert_eq!(flipped.draw.rotation, 0.0);
+        let turned = tile(level, 2, 2);
+        assert_eq!(turned.sprite.as_deref(), Some("terrain:1"));
+        assert!(!turned.solid);
+        assert!(!turned.draw.flip_x);
+        assert_eq!(turned.draw.rotation, 270.0);
+        let diagonal = tile(level, 2, 3);
+        assert!(diagonal.draw.flip_x);
+        assert_eq!(diagonal.draw.rotation, 270.0);
+
+        assert_eq!(level.tilesets.len(), 1);
+        let terrain = &level.tilesets[0];
+        assert_eq!(terrain.name, "terrain");
+        assert_eq!(terrain.image, fixture("terrain.png"));
+        assert_eq!((terrain.tile_width, terrain.tile_height), (40.0, 40.0));
+        assert_eq!((terrain.margin, terrain.spacing), (1.0, 2.0));
+
+        let start = level.spawns.player;
+        assert_eq!((start.row, start.col), (1, 0));
+        let mid = level.spawns.checkpoints["mid"];
+        assert_eq!((mid.row, mid.col), (1, 2));
+
+        let actions: Vec<_> = level.triggers.iter().map(|t| &t.action).collect();
+        assert_eq!(actions.len(), 4);
+        assert!(matches!(actions[0], TriggerAction::Checkpoint(name) if name == "mid"));
+        match actions[1] {
+            TriggerAction::Pan {
+                x,
+                y,
+                zoom,
+                duration,
+                hold,
+            } => assert_eq!(
+                (*x, *y, *zoom, *duration, *hold),
+                (3.0, 1.0, Some(1.5), 2.0, 1.0)
+            ),
+            _ => panic!("the 'look' trigger doesn't pan"),
+        }
+        assert!(matches!(
+            actions[2],
+            TriggerAction::Zoom { zoom, duration } if *zoom == 2.0 && *duration == 1.0
+        ));
+        assert!(matches!(actions[3], TriggerAction::Goal));
+        let goal = &level.triggers[3];
+        assert_eq!((goal.x, goal.y, goal.w, goal.h), (3.0, 1.0, 1.0, 2.0));
+
+        assert_eq!(level.collectibles.len(), 1);
+        let coin = &level.collectibles[0];
+        assert_eq!((coin.row, coin.col, coin.kind.as_str()), (0, 3, "coin"));
+
+        assert_eq!(level.enemies.len(), 2);
+        let bat = &level.enemies[0];
+        assert_eq!((bat.row, bat.col), (0, 2));
+        assert_eq!(bat.kind, EnemyKind::Flyer { dx: 2.0, dy: 0.0 });
+        // a tile object is placed by its bottom left corner
+        let goomba = &level.enemies[1];
+        assert_eq!((goomba.row, goomba.col), (0, 1));
+        assert_eq!(goomba.kind, EnemyKind::Walker);
+
+        assert_eq!(level.warnings, ["object 'thing' has unknown type 'bogus'"]);
+    }
+
+    #[test]
+    fn json_maps_load() {
+        check(&load(&fixture("level.tmj")).unwrap());
+    }
+
+    #[test]
+    fn xml_maps_load() {
+        check(&load(&fixture("level.tmx")).unwrap());
+    }
+
+    #[test]
+    fn tile_layers_need_a_width() {
+        let err = load(&fixture("no_width.tmj")).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "tests/fixtures/tiled/no_width.tmj: layer 'ground' has no width"
+        );
+    }
+
+    #[test]
+    fn tileset_images_need_a_tile_size() {
+        let err = load(&fixture("no_tile_size.tmx")).err().unwrap();
+        let needs = "tileset 'terrain' needs a tilewidth and tileheight above 0";
+        assert!(err.to_string().ends_with(needs), "{}", err);
+    }
+
+    #[test]
+    fn the_game_map_loads() {
+        let level = load(Path::new("assets/map.tmj")).unwrap();
+        assert!(level.warnings.is_empty(), "{:?}", level.warnings);
+        assert!(level
+            .triggers
+            .iter()
+            .any(|trigger| matches!(trigger.action, TriggerAction::Goal)));
+    }
+}
@@ -0,0 +1,710 @@
+use crate::animation::Animator;
+use crate::assets::{Handle, Registry};
+use crate::enemy::Enemy;
+use crate::grid::Grid;
+use crate::legend::BumpBehavior;
+use crate::level::{Level, TriggerAction};
+use crate::libs::Rect;
+use crate::object::{BumpSprites, Object};
+use crate::player::{Player, PlayerPhysics, MAX_HEALTH};
+use crate::replay::Tick;
+use crate::score::Score;
+use crate::sprite::Sprite;
+use std::collections::BTreeSet;
+
+/// Something that happened during a step which the game reacts to outside
+/// of the simulation, e.g. with the camera.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+    /// The player hit its head on something.
+    Bumped,
+    /// Object `id` broke; its pieces should fly off from where it was.
+    Broke { id: usize },
+    /// Object `id` bounced up from being bumped.
+    Bounced { id: usize },
+    /// The player picked up an item of `kind`.
+    Collected { kind: String },
+    /// The player landed on enemy `id` and defeated it.
+    Stomped { id: usize },
+    /// The player ran into an enemy and lost health.
+    Hurt,
+    /// The player lost all its health or fell out of the level, losing a
+    /// life.
+    Died,
+    /// The player went back to its checkpoint or the start.
+    Respawned,
+    /// The player reached the goal.
+    Cleared,
+    /// The player walked into a trigger showing `x`, `y`, in pixels.
+    Pan {
+        x: f64,
+        y: f64,
+        zoom: Option<f64>,
+        duration: f64,
+        hold: f64,
+    },
+    /// The player walked into a trigger zooming around `focus`, in pixels.
+    Zoom {
+        zoom: f64,
+        duration: f64,
+        focus: (f64, f64),
+    },
+}
+
+/// Pixels per second items rise out of the block that let them out.
+const ITEM_RISE_SPEED: f64 = 80.0;
+/// How far into an enemy the player's feet can be for landing on it to
+/// count as a stomp rather than running into it.
+const STOMP_DEPTH: f64 = 16.0;
+/// Speed the player bounces off a stomped enemy at.
+const STOMP_BOUNCE: f64 = 400.0;
+
+/// Something let out of a block, which the player picks up by touching it.
+pub struct Item {
+    /// What the game makes of picking the item up, and what its sprite is
+    /// called.
+    pub kind: String,
+    pub sprite: Option<Handle<Sprite>>,
+    pub rect: Rect,
+    /// Pixels left to rise out of the block.
+    rise: f64,
+}
+
+/// Everything that plays out in a level, along with the sprites it is drawn
+/// with, which it only holds on to, so it can be stepped without a window.
+pub struct World {
+    pub level: Level,
+    pub objects: Vec<Object>,
+    pub player: Player,
+    pub player_sprite: Option<Handle<Sprite>>,
+    pub items: Vec<Item>,
+    pub enemies: Vec<Enemy>,
+    pub score: Score,
+    /// Name of the last checkpoint reached.
+    pub checkpoint: Option<String>,
+    grid: Grid,
+    /// Triggers the player is standing in, and pans already shown.
+    inside: BTreeSet<usize>,
+    panned: BTreeSet<usize>,
+}
+
+impl World {
+    /// Places the tiles of `level` and the player at its start, looking up
+    /// the sprites of everything in `sprites` once and for all.
+    pub fn new(
+        level: Level,
+        physics: PlayerPhysics,
+        animator: Animator,
+        sprites: &Registry<Sprite>,
+    ) -> World {
+        let sprite = |name: &str| sprites.get(name);
+        let objects: Vec<Object> = level
+            .tiles
+            .iter()
+            .map(|tile| {
+                let rect = Rect::new(
+                    tile.col as f64 * 40.0,
+                    tile.row as f64 * 40.0,
+                    0.0,
+                    0.0,
+                    40.0,
+                );
+                let mut object = Object::new(
+                    tile.sprite.as_deref().and_then(sprite),
+                    rect,
+                    tile.solid,
+                    tile.draw,
+                    tile.bump.clone(),
+                );
+                if let BumpBehavior::Item { spawn, used } = &tile.bump {
+                    object.bump_sprites = BumpSprites {
+                        used: sprite(used),
+                        item: sprite(spawn),
+                    };
+                }
+                object
+            })
+            .collect();
+
+        let mut grid = Grid::new(40.0, level.width, level.height);
+        for (id, object) in objects.iter().enumerate().filter(|(_, o)| o.solid) {
+            grid.insert(id, &object.rect);
+        }
+
+        let items = level
+            .collectibles
+            .iter()
+            .map(|collectible| Item {
+                kind: collectible.kind.clone(),
+                sprite: sprite(&collectible.kind),
+                rect: Rect::new(
+                    collectible.col as f64 * 40.0,
+                    collectible.row as f64 * 40.0,
+                    0.0,
+                    0.0,
+                    40.0,
+                ),
+                rise: 0.0,
+            })
+            .collect();
+
+        let enemies = level
+            .enemies
+            .iter()
+            .map(|spawn| {
+                let (x, y) = (spawn.col as f64 * 40.0, spawn.row as f64 * 40.0);
+                let rect = Rect::new(x, y, 5.0, 0.0, 40.0);
+                Enemy::new(spawn.kind.clone(), sprite(spawn.kind.name()), rect)
+            })
+            .collect();
+
+        let (x, y) = level.spawns.player.position(40.0);
+        let player = Player::new(Rect::new(x, y, 5.0, 0.0, 40.0), animator, physics);
+
+        World {
+            level,
+            objects,
+            player,
+            player_sprite: sprite("player"),
+            items,
+            enemies,
+            score: Score::default(),
+            checkpoint: None,
+            grid,
+            inside: BTreeSet::new(),
+            panned: BTreeSet::new(),
+        }
+    }
+
+    /// Simulates `dt` seconds with the input of `tick`.
+    pub fn step(&mut self, dt: f64, tick: Tick) -> Vec<WorldEvent> {
+        let mut events = Vec::new();
+        self.score.time += dt;
+
+        if tick.respawn {
+            self.respawn(&mut events);
+        }
+        let player = &mut self.player;
+        player.controller = tick.controller();
+        player.update(dt, &self.objects, &self.grid);
+        // of the blocks above its head, the player hits the one most under it
+        let center = player.rect.center();
+        let bumped = player.bumped().into_iter().min_by(|a, b| {
+            let distance = |id: &usize| (self.objects[*id].rect.center().x - center.x).abs();
+            distance(a).total_cmp(&distance(b))
+        });
+        if let Some(id) = bumped {
+            events.push(WorldEvent::Bumped);
+            self.bump(id, &mut events);
+        }
+
+        for item in self.items.iter_mut() {
+            let rise = item.rise.min(ITEM_RISE_SPEED * dt);
+            item.rect.y -= rise;
+            item.rise -= rise;
+        }
+        // below the level there is nothing to land on
+        let floor = self.level.height as f64 * 40.0;
+        for (id, enemy) in self.enemies.iter_mut().enumerate() {
+            if enemy.defeated {
+                continue;
+            }
+            enemy.update(dt, &self.objects, &self.grid);
+            if enemy.rect.top() > floor {
+                enemy.defeated = true;
+                continue;
+            }
+            let player = &mut self.player;
+            if !overlaps(&enemy.rect, &player.rect) {
+                continue;
+            }
+            if player.vel.y > 0.0 && player.rect.bottom() - enemy.rect.top() < STOMP_DEPTH {
+                enemy.defeated = true;
+                player.bounce(STOMP_BOUNCE);
+                self.score.stomped();
+                events.push(WorldEvent::Stomped { id });
+            } else if player.hurt(enemy.rect.center().x) {
+                events.push(WorldEvent::Hurt);
+            }
+        }
+        if self.player.health == 0 || self.player.rect.top() > floor {
+            self.score.lives = self.score.lives.saturating_sub(1);
+            self.score.deaths += 1;
+            self.player.health = MAX_HEALTH;
+            events.push(WorldEvent::Died);
+            self.respawn(&mut events);
+        }
+
+        let player = &self.player.rect;
+        let touching = |item: &Item| overlaps(&item.rect, player);
+        for item in self.items.iter().filter(|item| touching(item)) {
+            self.score.collect(&item.kind);
+            events.push(WorldEvent::Collected {
+                kind: item.kind.clone(),
+            });
+        }
+        self.items.retain(|item| !touching(item));
+
+        let center = self.player.rect.center();
+        let (x, y) = (center.x / 40.0, center.y / 40.0);
+        for (idx, trigger) in self.level.triggers.iter().enumerate() {
+            if !trigger.contains(x, y) {
+                self.inside.remove(&idx);
+                continue;
+            }
+            if !self.inside.insert(idx) {
+                continue;
+            }
+            match &trigger.action {
+                TriggerAction::Checkpoint(name) => self.checkpoint = Some(name.clone()),
+                TriggerAction::Pan {
+                    x,
+                    y,
+                    zoom,
+                    duration,
+                    hold,
+                } => {
+                    if self.panned.insert(idx) {
+                        events.push(WorldEvent::Pan {
+                            x: x * 40.0,
+                            y: y * 40.0,
+                            zoom: *zoom,
+                            duration: *duration,
+                            hold: *hold,
+                        });
+                    }
+                }
+                TriggerAction::Zoom { zoom, duration } => events.push(WorldEvent::Zoom {
+                    zoom: *zoom,
+                    duration: *duration,
+                    focus: (center.x, center.y),
+                }),
+                TriggerAction::Goal => events.push(WorldEvent::Cleared),
+            }
+        }
+        events
+    }
+
+    /// Puts the player back at its last checkpoint, or the start.
+    fn respawn(&mut self, events: &mut Vec<WorldEvent>) {
+        let spawns = &self.level.spawns;
+        let point = self
+            .checkpoint
+            .as_ref()
+            .and_then(|name| spawns.checkpoints.get(name))
+            .unwrap_or(&spawns.player);
+        let (x, y) = point.position(40.0);
+        self.player.respawn(x, y);
+        events.push(WorldEvent::Respawned);
+    }
+
+    /// Makes object `id` react to being hit from below.
+    fn bump(&mut self, id: usize, events: &mut Vec<WorldEvent>) {
+        let object = &mut self.objects[id];
+        match object.bump.clone() {
+            BumpBehavior::None => {}
+            BumpBehavior::Break => {
+                object.removed = true;
+                object.solid = false;
+                self.grid.remove(id, &object.rect);
+                self.score.broke_brick();
+                events.push(WorldEvent::Broke { id });
+            }
+            BumpBehavior::Item { spawn, .. } => {
+                let sprites = std::mem::take(&mut object.bump_sprites);
+                object.sprite = sprites.used;
+                object.bump = BumpBehavior::None;
+                self.items.push(Item {
+                    kind: spawn,
+                    sprite: sprites.item,
+                    rect: object.rect.clone(),
+                    rise: object.rect.scale,
+                });
+                events.push(WorldEvent::Bounced { id });
+            }
+        }
+    }
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.left() < b.right() && a.right() > b.left() && a.top() < b.bottom() && a.bottom() > b.top()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legend::Legend;
+    use crate::libs::Tilemap;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    const STEP: f64 = 1.0 / 60.0;
+
+    fn world(map: &str) -> World {
+        world_with(map, &Registry::default())
+    }
+
+    /// A world of `map` which looks its sprites up in `sprites`.
+    fn world_with(map: &str, sprites: &Registry<Sprite>) -> World {
+        let legend = Legend::load(Path::new("assets/tiles.toml")).unwrap();
+        let level = Level::from_tilemap(&Tilemap::parse(map), &legend).unwrap();
+        let animator = Animator::new(BTreeMap::new(), "idle");
+        World::new(level, PlayerPhysics::default(), animator, sprites)
+    }
+
+    fn run(world: &mut World, tick: Tick, seconds: f64) {
+        for _ in 0..(seconds / STEP).round() as usize {
+            world.step(STEP, tick);
+        }
+    }
+
+    fn jump() -> Tick {
+        Tick {
+            jump: true,
+            ..Tick::default()
+        }
+    }
+
+    /// Highest the player gets, in pixels above where it started, while
+    /// `ticks` play one after the other.
+    fn peak(world: &mut World, ticks: &[Tick]) -> f64 {
+        let start = world.player.rect.y;
+        let mut top = start;
+        for tick in ticks.iter() {
+            world.step(STEP, *tick);
+            top = top.min(world.player.rect.y);
+        }
+        start - top
+    }
+
+    /// Steps with `ticks` one after the other, returning what happened.
+    fn play(world: &mut World, ticks: &[Tick]) -> Vec<WorldEvent> {
+        ticks
+            .iter()
+            .flat_map(|tick| world.step(STEP, *tick))
+            .collect()
+    }
+
+    /// Id of the first object placed from a `sprite` tile.
+    fn object(world: &World, sprite: &str) -> usize {
+        world
+            .level
+            .tiles
+            .iter()
+            .position(|tile| tile.sprite.as_deref() == Some(sprite))
+            .unwrap()
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_ground() {
+        let mut world = world("=====\n==P==\n=====\n=====\n11111");
+        run(&mut world, Tick::default(), 2.0);
+        let player = &world.player;
+        assert!(player.grounded());
+        assert_eq!(player.rect.bottom(), 160.0);
+        assert_eq!(player.vel.y, 0.0);
+    }
+
+    #[test]
+    fn stops_against_a_wall() {
+        let mut world = world("========\n=P====1=\n11111111");
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        run(&mut world, right, 3.0);
+        let player = &world.player;
+        assert_eq!(player.rect.right(), 240.0);
+        assert_eq!(player.vel.x, 0.0);
+        assert!(player.grounded());
+    }
+
+    #[test]
+    fn jumps_and_lands_back_down() {
+        let mut world = world("=====\n=====\n=====\n=====\n=====\n==P==\n11111");
+        run(&mut world, Tick::default(), 0.5);
+        let ground = world.player.rect.y;
+
+        let height = peak(&mut world, &[jump(); 30]);
+        // v² / 2g with the default physics
+        assert!((height - 150.0).abs() < 10.0, "jumped {} pixels", height);
+        run(&mut world, Tick::default(), 1.0);
+        assert!(world.player.grounded());
+        assert_eq!(world.player.rect.y, ground);
+    }
+
+    #[test]
+    fn releasing_jump_early_jumps_lower() {
+        let map = "=====\n=====\n=====\n=====\n=====\n==P==\n11111";
+        let mut held = world(map);
+        let mut tapped = world(map);
+        run(&mut held, Tick::default(), 0.5);
+        run(&mut tapped, Tick::default(), 0.5);
+
+        let mut tap = vec![jump(); 5];
+        tap.resize(30, Tick::default());
+        let full = peak(&mut held, &[jump(); 30]);
+        let short = peak(&mut tapped, &tap);
+        assert!(short < full / 2.0, "tapped {} and held {}", short, full);
+    }
+
+    #[test]
+    fn jump_pressed_just_before_landing_is_kept() {
+        let mut world = world("=====\n==P==\n=====\n11111");
+        // fall until a few steps above the ground
+        while world.player.rect.bottom() < 120.0 - 10.0 {
+            world.step(STEP, Tick::default());
+        }
+        assert!(!world.player.grounded());
+        let height = peak(&mut world, &[jump(); 30]);
+        assert!(height > 100.0, "jumped {} pixels", height);
+    }
+
+    #[test]
+    fn can_still_jump_just_after_walking_off_a_ledge() {
+        let mut world = world("======\n=P====\n11====\n======\n======\n======");
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        run(&mut world, Tick::default(), 0.5);
+        while world.player.grounded() {
+            world.step(STEP, right);
+        }
+        assert!(world.player.rect.left() >= 80.0);
+        let height = peak(&mut world, &[jump(); 30]);
+        assert!(height > 100.0, "jumped {} pixels", height);
+    }
+
+    #[test]
+    fn without_any_grace_jumps_need_the_ground_and_a_fresh_press() {
+        let map = "=====\n==P==\n=====\n=====\n=====\n11111";
+        let mut world = world(map);
+        world.player.physics = PlayerPhysics {
+            jump_buffer: 0.0,
+            coyote_time: 0.0,
+            ..PlayerPhysics::default()
+        };
+        // pressed in the air, the jump is forgotten before landing
+        let height = peak(&mut world, &[jump(); 60]);
+        assert_eq!(height, 0.0);
+        assert!(world.player.grounded());
+
+        // but a press on the ground still jumps
+        world.step(STEP, Tick::default());
+        let height = peak(&mut world, &[jump(); 30]);
+        assert!(height > 100.0, "jumped {} pixels", height);
+    }
+
+    #[test]
+    fn bumping_a_brick_breaks_it() {
+        let mut world = world("=====\n=====\n==2==\n=====\n==P==\n11111");
+        let brick = object(&world, "brick");
+        run(&mut world, Tick::default(), 0.5);
+
+        let events = play(&mut world, &[jump(); 30]);
+        assert!(events.contains(&WorldEvent::Broke { id: brick }));
+        assert!(world.objects[brick].removed);
+        // nothing stops the next jump where the brick was
+        run(&mut world, Tick::default(), 1.0);
+        let height = peak(&mut world, &[jump(); 30]);
+        assert!((height - 150.0).abs() < 10.0, "jumped {} pixels", height);
+    }
+
+    #[test]
+    fn bumping_a_question_block_lets_out_one_item() {
+        let mut sprites = Registry::default();
+        let used = sprites.insert("used_block".to_owned(), Sprite::default());
+        let coin = sprites.insert("coin".to_owned(), Sprite::default());
+        let mut world = world_with("=====\n=====\n==?==\n=====\n==P==\n11111", &sprites);
+        let block = object(&world, "brick2");
+        assert!(world.objects[block].sprite.is_none());
+        run(&mut world, Tick::default(), 0.5);
+
+        let events = play(&mut world, &[jump(); 30]);
+        assert!(events.contains(&WorldEvent::Bounced { id: block }));
+        assert!(world.objects[block].sprite == Some(used));
+        assert!(!world.objects[block].removed);
+        run(&mut world, Tick::default(), 1.0);
+        assert_eq!(world.items.len(), 1);
+        assert_eq!(world.items[0].kind, "coin");
+        assert!(world.items[0].sprite == Some(coin));
+        // risen out to sit on top of the block
+        assert!((world.items[0].rect.y - 40.0).abs() < 1e-9);
+
+        let events = play(&mut world, &[jump(); 30]);
+        assert!(events.contains(&WorldEvent::Bumped));
+        assert!(!events.contains(&WorldEvent::Bounced { id: block }));
+        assert_eq!(world.items.len(), 1);
+    }
+
+    #[test]
+    fn walking_into_a_coin_collects_it() {
+        let mut world = world("=====\n=P=o=\n11111");
+        assert_eq!(world.items.len(), 1);
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        let events = play(&mut world, &[right; 60]);
+        assert!(events.contains(&WorldEvent::Collected {
+            kind: "coin".to_owned()
+        }));
+        assert!(world.items.is_empty());
+        assert_eq!(world.score.coins, 1);
+        assert!(world.score.points > 0);
+    }
+
+    #[test]
+    fn walkers_turn_at_ledges() {
+        let mut world = world("P=======\n1===w===\n1==1111=");
+        let (mut left, mut right) = (f64::INFINITY, f64::NEG_INFINITY);
+        for _ in 0..600 {
+            world.step(STEP, Tick::default());
+            let walker = &world.enemies[0].rect;
+            left = left.min(walker.left());
+            right = right.max(walker.right());
+        }
+        // the ground below runs from 120 to 280
+        assert!((119.0..130.0).contains(&left), "walked left to {}", left);
+        assert!((270.0..281.0).contains(&right), "walked right to {}", right);
+        assert_eq!(world.enemies[0].rect.bottom(), 80.0);
+    }
+
+    #[test]
+    fn hoppers_wait_hop_and_turn_at_walls() {
+        let mut world = world("P=========\n1===1==h=1\n1111111111");
+        run(&mut world, Tick::default(), 0.5);
+        let hopper = &world.enemies[0].rect;
+        let (x, ground) = (hopper.x, hopper.y);
+        assert_eq!(hopper.bottom(), 80.0);
+
+        // waits a second on the ground before each hop
+        run(&mut world, Tick::default(), 0.4);
+        assert_eq!(world.enemies[0].rect.x, x);
+        let (mut left, mut right, mut top) = (f64::INFINITY, f64::NEG_INFINITY, ground);
+        for _ in 0..1200 {
+            world.step(STEP, Tick::default());
+            let hopper = &world.enemies[0].rect;
+            left = left.min(hopper.left());
+            right = right.max(hopper.right());
+            top = top.min(hopper.y);
+        }
+        // hops about v² / 2g high, between the walls at 200 and 360
+        let height = ground - top;
+        assert!((height - 84.0).abs() < 5.0, "hopped {} pixels", height);
+        assert!((200.0..205.0).contains(&left), "hopped left to {}", left);
+        assert!((355.0..361.0).contains(&right), "hopped right to {}", right);
+    }
+
+    #[test]
+    fn flyers_patrol_their_path() {
+        let mut world = world("P=======\n1=f=====");
+        let (mut left, mut right) = (f64::INFINITY, f64::NEG_INFINITY);
+        for _ in 0..600 {
+            world.step(STEP, Tick::default());
+            let flyer = &world.enemies[0].rect;
+            left = left.min(flyer.x);
+            right = right.max(flyer.x);
+            assert_eq!(flyer.y, 40.0);
+        }
+        assert!((left - 80.0).abs() < 2.0, "flew left to {}", left);
+        assert!((right - 240.0).abs() < 2.0, "flew right to {}", right);
+    }
+
+    #[test]
+    fn landing_on_an_enemy_defeats_it() {
+        let mut world = world("==P==\n=====\n=====\n=1w1=\n11111");
+        let events = play(&mut world, &[Tick::default(); 60]);
+        assert!(events.contains(&WorldEvent::Stomped { id: 0 }));
+        assert!(!events.contains(&WorldEvent::Hurt));
+        assert!(world.enemies[0].defeated);
+        assert!(world.score.points > 0);
+    }
+
+    /// Steps until the player runs into the enemy, returning what happened
+    /// on that step.
+    fn run_into_enemy(world: &mut World) -> Vec<WorldEvent> {
+        let start = world.score.time;
+        loop {
+            assert!(world.score.time - start < 5.0, "never got hurt");
+            let events = world.step(STEP, Tick::default());
+            if events.contains(&WorldEvent::Hurt) {
+                return events;
+            }
+        }
+    }
+
+    #[test]
+    fn running_into_an_enemy_hurts_and_knocks_back() {
+        let mut world = world("======\nP===w1\n111111");
+        run_into_enemy(&mut world);
+        assert_eq!(world.player.health, MAX_HEALTH - 1);
+        assert!(world.player.vel.x < 0.0 && world.player.vel.y < 0.0);
+        assert_eq!(world.score.lives, Score::default().lives);
+        assert!(!world.enemies[0].defeated);
+
+        // hurt once, the player can't be hurt again for a while
+        let events = play(&mut world, &[Tick::default(); 30]);
+        assert!(!events.contains(&WorldEvent::Hurt));
+        assert_eq!(world.player.health, MAX_HEALTH - 1);
+    }
+
+    #[test]
+    fn losing_all_health_costs_a_life() {
+        let mut world = world("======\nP===w1\n111111");
+        world.player.health = 1;
+        let events = run_into_enemy(&mut world);
+        assert!(events.contains(&WorldEvent::Died));
+        assert!(events.contains(&WorldEvent::Respawned));
+        assert_eq!(world.player.health, MAX_HEALTH);
+        assert_eq!(world.score.lives, Score::default().lives - 1);
+        assert_eq!(world.score.deaths, 1);
+    }
+
+    #[test]
+    fn falling_out_of_the_level_respawns_at_the_checkpoint() {
+        let mut world = world("=====\nP=a==\n111==\n=====");
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        let mut events = Vec::new();
+        while !events.contains(&WorldEvent::Died) {
+            assert!(world.score.time < 5.0, "never fell out");
+            events = world.step(STEP, right);
+        }
+        assert_eq!(world.checkpoint.as_deref(), Some("a"));
+        assert_eq!((world.player.rect.x, world.player.rect.y), (80.0, 40.0));
+        assert_eq!(world.score.deaths, 1);
+    }
+
+    #[test]
+    fn reaching_the_goal_clears_the_level_once() {
+        let mut world = world("======\nP===G=\n111111");
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        let mut cleared = 0;
+        for _ in 0..120 {
+            let events = world.step(STEP, right);
+            cleared += events.iter().filter(|e| **e == WorldEvent::Cleared).count();
+        }
+        assert_eq!(cleared, 1);
+    }
+
+    #[test]
+    fn only_the_goal_clears_the_level() {
+        let mut world = world("======\nP====2\n111111");
+        let right = Tick {
+            right: true,
+            ..Tick::default()
+        };
+        let events = play(&mut world, &[right; 120]);
+        // pressed against the wall at the end of the row
+        assert!(!events.contains(&WorldEvent::Died));
+        assert_eq!(world.player.rect.right(), 200.0);
+        assert!(!events.contains(&WorldEvent::Cleared));
+    }
+}